@@ -0,0 +1,56 @@
+//! Times the cascade over a document of a few thousand elements styled by
+//! hundreds of rules, most of them class selectors. Run with
+//! `cargo bench --bench cascade`.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use stupid_webbrowser::dom::{parse_css, style_tree, ParserBuilder};
+
+const RULES: usize = 500;
+const ELEMENTS: usize = 2_000;
+const ITERATIONS: u32 = 20;
+
+fn stylesheet_source() -> String {
+    let mut css = String::new();
+    for i in 0..RULES {
+        let selector = match i % 4 {
+            0 => format!(".c{i}"),
+            1 => format!("div.c{i}.c{}", i + 1),
+            2 => format!("section .c{i} > span"),
+            _ => format!("#e{i}, p.c{i}"),
+        };
+        css.push_str(&format!("{selector} {{ margin: {}px; }}\n", i % 10));
+    }
+    css
+}
+
+fn document_source() -> String {
+    let mut html = String::from("<section>");
+    for i in 0..ELEMENTS {
+        html.push_str(&format!(
+            "<div id=\"e{i}\" class=\"c{} c{} item\"><span>{i}</span></div>",
+            i % RULES,
+            (i * 7) % RULES,
+        ));
+    }
+    html.push_str("</section>");
+    html
+}
+
+fn main() {
+    let stylesheet = parse_css(&stylesheet_source()).unwrap();
+    let root = ParserBuilder::new().parse(document_source()).unwrap();
+    let stylesheets = [stylesheet];
+
+    black_box(style_tree(&root, &stylesheets));
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(style_tree(black_box(&root), &stylesheets));
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    println!(
+        "cascade: {RULES} rules over {} elements: {elapsed:?} per style tree",
+        ELEMENTS * 2 + 1
+    );
+}
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use stupid_webbrowser::dom::ParserBuilder;
+
+fuzz_target!(|data: &[u8]| {
+    // Any input may be rejected with a ParseError, but must never panic.
+    let input = String::from_utf8_lossy(data).into_owned();
+    let _ = ParserBuilder::new().parse(input);
+});
@@ -0,0 +1,111 @@
+#![no_main]
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+
+use stupid_webbrowser::dom::ParserBuilder;
+
+const TAGS: &[&str] = &[
+    "div", "p", "span", "b", "i", "ul", "li", "a", "table", "td", "custom-el", "br", "img",
+    "svg", "rect", "math", "title", "textarea", "script", "style", "pre",
+];
+const VOID: &[&str] = &["br", "img"];
+const RAW_TEXT: &[&str] = &["script", "style"];
+const ESCAPABLE_RAW_TEXT: &[&str] = &["title", "textarea"];
+const ATTRIBUTES: &[&str] = &["id", "class", "href", "data-x", "title", "hidden"];
+
+/// A tree the generator can turn into well-formed HTML.
+#[derive(Arbitrary, Debug)]
+enum GenNode {
+    Text(String),
+    Comment(String),
+    Element {
+        tag: u8,
+        attributes: Vec<(u8, String)>,
+        children: Vec<GenNode>,
+    },
+}
+
+fn escape(text: &str, in_attribute: bool) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' if in_attribute => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn text_of(nodes: &[GenNode]) -> String {
+    nodes
+        .iter()
+        .map(|node| match node {
+            GenNode::Text(text) => text.as_str(),
+            _ => "",
+        })
+        .collect()
+}
+
+fn render(node: &GenNode, in_foreign_content: bool, html: &mut String) {
+    match node {
+        GenNode::Text(text) => html.push_str(&escape(text, false)),
+        GenNode::Comment(data) => {
+            let data: String = data.chars().filter(|&c| c != '-' && c != '>').collect();
+            html.push_str(&format!("<!--{}-->", data));
+        }
+        GenNode::Element {
+            tag,
+            attributes,
+            children,
+        } => {
+            let tag = TAGS[*tag as usize % TAGS.len()];
+            html.push('<');
+            html.push_str(tag);
+            for (name, value) in attributes {
+                let name = ATTRIBUTES[*name as usize % ATTRIBUTES.len()];
+                html.push_str(&format!(" {}=\"{}\"", name, escape(value, true)));
+            }
+            html.push('>');
+            let foreign = in_foreign_content || tag == "svg" || tag == "math";
+            if VOID.contains(&tag) && !foreign {
+                return;
+            }
+            if RAW_TEXT.contains(&tag) {
+                // Raw text can hold anything but its own closing tag.
+                html.push_str(&text_of(children).replace("</", "< /"));
+            } else if ESCAPABLE_RAW_TEXT.contains(&tag) {
+                html.push_str(&escape(&text_of(children), false));
+            } else {
+                for child in children {
+                    render(child, foreign, html);
+                }
+            }
+            html.push_str(&format!("</{}>", tag));
+        }
+    }
+}
+
+fuzz_target!(|nodes: Vec<GenNode>| {
+    // Serializing a parsed tree and parsing it again must give the same tree,
+    // compared through its serialization.
+    let mut html = String::new();
+    for node in &nodes {
+        render(node, false, &mut html);
+    }
+    let parser = ParserBuilder::new();
+    // The generator ignores content models, so it can write HTML such as
+    // `<p><div></div></p>`, where the `<div>` ends the `<p>` and leaves its
+    // end tag stray. Only what parses has to round-trip.
+    let Ok(first) = parser.parse(html.clone()) else {
+        return;
+    };
+    let serialized = first.to_html();
+    let second = parser
+        .parse(serialized.clone())
+        .unwrap_or_else(|e| panic!("serialized HTML failed to parse: {e}\n{serialized}"));
+    assert_eq!(second.to_html(), serialized, "from {html}");
+});
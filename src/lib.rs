@@ -0,0 +1,8236 @@
+pub mod dom {
+    use std::collections::{HashMap, HashSet};
+    use std::fmt;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    pub struct Parser {
+        pos: usize,
+        input: String,
+        options: ParserOptions,
+        depth: usize,
+        node_count: usize,
+        attribute_count: usize,
+        /// Inside `<svg>` or `<math>`, where any element may self-close.
+        in_foreign_content: bool,
+        /// Parsing the argument of a `:not()` selector.
+        in_negation: bool,
+        /// Tag names of the elements currently being parsed, outermost first.
+        open_elements: Vec<String>,
+        warnings: Vec<Warning>,
+        /// The first `<!doctype ...>` seen, without the `<!doctype` and `>`.
+        doctype: Option<String>,
+        /// Formatting elements such as `<b>` that `recover` closed early
+        /// because of misnested tags, innermost first. They're reopened
+        /// around the next text, as browsers do.
+        reopen: Vec<(String, AttrMap)>,
+        /// Tag and attribute names seen so far, when `intern_names` is set.
+        names: HashSet<Rc<str>>,
+        /// With `keep_raw_attributes`, the attributes of each start tag not
+        /// yet built into an element, keyed by where its name starts.
+        raw_attributes: HashMap<usize, Vec<(String, String)>>,
+    }
+
+    #[derive(Debug, Clone)]
+    struct ParserOptions {
+        strip_namespaces: bool,
+        lowercase_tags: bool,
+        implied_structure: bool,
+        max_depth: usize,
+        max_nodes: usize,
+        max_attributes: usize,
+        max_input_size: usize,
+        recover: bool,
+        void_elements: HashSet<String>,
+        collapse_whitespace: bool,
+        max_selectors: usize,
+        max_combinators: usize,
+        intern_names: bool,
+        custom_entities: HashMap<String, String>,
+        keep_raw_attributes: bool,
+        /// Parse as XML, for `validate_xml`: every element is treated like
+        /// foreign content, and what XML forbids but the parser can step
+        /// past is recorded as a warning.
+        xml: bool,
+        /// Never fail, for `parse_lossy`: on top of what `recover` repairs,
+        /// every other error becomes a warning and is stepped past.
+        lossy: bool,
+    }
+
+    impl Default for ParserOptions {
+        fn default() -> ParserOptions {
+            ParserOptions {
+                strip_namespaces: false,
+                lowercase_tags: false,
+                implied_structure: false,
+                max_depth: 512,
+                max_nodes: 1_000_000,
+                max_attributes: 1_000,
+                max_input_size: 64 * 1024 * 1024,
+                recover: false,
+                void_elements: VOID_ELEMENTS.iter().map(|tag| tag.to_string()).collect(),
+                collapse_whitespace: false,
+                max_selectors: 10_000,
+                max_combinators: 32,
+                intern_names: false,
+                custom_entities: HashMap::new(),
+                keep_raw_attributes: false,
+                xml: false,
+                lossy: false,
+            }
+        }
+    }
+
+    /// Configures how HTML is parsed.
+    #[derive(Debug, Clone, Default)]
+    pub struct ParserBuilder {
+        options: ParserOptions,
+    }
+
+    impl ParserBuilder {
+        pub fn new() -> ParserBuilder {
+            ParserBuilder::default()
+        }
+
+        /// Drops the namespace prefix from tag names, so `<svg:rect>` becomes
+        /// `rect`. Off by default, which keeps the full `svg:rect` name.
+        pub fn strip_namespaces(mut self, strip: bool) -> ParserBuilder {
+            self.options.strip_namespaces = strip;
+            self
+        }
+
+        /// Lowercases the names of known HTML elements, so `<DIV>` becomes
+        /// `div`. Other names, such as SVG's `linearGradient` and `clipPath`,
+        /// are case-sensitive and keep their case. Off by default.
+        pub fn lowercase_tags(mut self, lowercase: bool) -> ParserBuilder {
+            self.options.lowercase_tags = lowercase;
+            self
+        }
+
+        /// Always produces an `html` element holding a `head` and a `body`,
+        /// as HTML5 tree construction does. Leading `<title>`, `<meta>`,
+        /// `<link>` and similar elements go into the head and everything else
+        /// into the body. Off by default because it changes the tree's shape.
+        pub fn implied_structure(mut self, implied: bool) -> ParserBuilder {
+            self.options.implied_structure = implied;
+            self
+        }
+
+        /// How deeply elements may nest before parsing fails. This keeps
+        /// hostile input from overflowing the stack. Defaults to 512.
+        pub fn max_depth(mut self, max_depth: usize) -> ParserBuilder {
+            self.options.max_depth = max_depth;
+            self
+        }
+
+        /// How many nodes (elements, text and comments) a document may
+        /// contain. Guards against very wide input. Defaults to 1,000,000.
+        pub fn max_nodes(mut self, max_nodes: usize) -> ParserBuilder {
+            self.options.max_nodes = max_nodes;
+            self
+        }
+
+        /// How many attributes a single element may have. Defaults to 1,000.
+        pub fn max_attributes(mut self, max_attributes: usize) -> ParserBuilder {
+            self.options.max_attributes = max_attributes;
+            self
+        }
+
+        /// The largest input, in bytes, that will be parsed at all. Defaults
+        /// to 64 MiB.
+        pub fn max_input_size(mut self, max_input_size: usize) -> ParserBuilder {
+            self.options.max_input_size = max_input_size;
+            self
+        }
+
+        /// Repairs broken tag structure instead of failing, the way browsers
+        /// do: an element left open is closed when its parent closes or the
+        /// input ends, a closing tag that matches no open element is ignored,
+        /// and a `<` that can't start a tag, as in `a < b`, is kept as text.
+        /// Other errors are still fatal. Off by default.
+        pub fn recover(mut self, recover: bool) -> ParserBuilder {
+            self.options.recover = recover;
+            self
+        }
+
+        /// Replaces the set of elements that never have content or a closing
+        /// tag, compared ignoring case. Pass an empty set for XML, where every
+        /// element must be closed. Defaults to HTML's `br`, `img`, `input`
+        /// and so on.
+        pub fn void_elements(mut self, void_elements: HashSet<String>) -> ParserBuilder {
+            self.options.void_elements = void_elements;
+            self
+        }
+
+        /// Collapses each run of whitespace in text to a single space, as
+        /// rendering does, except inside `<pre>`. Entities are decoded first,
+        /// and non-breaking spaces (`&nbsp;`) are not whitespace here, so
+        /// `a&nbsp;&nbsp;b` keeps both. Off by default.
+        pub fn collapse_whitespace(mut self, collapse: bool) -> ParserBuilder {
+            self.options.collapse_whitespace = collapse;
+            self
+        }
+
+        /// How many selectors one comma-separated list may hold, in CSS
+        /// parsed with `parse_css`. Defaults to 10,000.
+        pub fn max_selectors(mut self, max_selectors: usize) -> ParserBuilder {
+            self.options.max_selectors = max_selectors;
+            self
+        }
+
+        /// How many combinators one selector may chain, as in `a > b c`.
+        /// Matching long chains of descendant combinators can take time
+        /// exponential in their length, so keep this low for untrusted CSS.
+        /// Defaults to 32.
+        pub fn max_combinators(mut self, max_combinators: usize) -> ParserBuilder {
+            self.options.max_combinators = max_combinators;
+            self
+        }
+
+        /// Shares one copy of each distinct tag and attribute name between
+        /// all the elements using it, so the thousands of `td`s in a large
+        /// table cost one allocation rather than one each. Each element still
+        /// stores a pointer, so this only pays off for repetitive documents.
+        /// Off by default.
+        pub fn intern_names(mut self, intern: bool) -> ParserBuilder {
+            self.options.intern_names = intern;
+            self
+        }
+
+        /// Extra named entities, such as those an XML document declares in
+        /// its DTD, mapped from name (without `&` and `;`) to replacement
+        /// text. They're looked up before the built-in HTML ones, so they can
+        /// also override those. Empty by default.
+        pub fn custom_entities(mut self, entities: HashMap<String, String>) -> ParserBuilder {
+            self.options.custom_entities = entities;
+            self
+        }
+
+        /// Also keeps each element's attributes exactly as its start tag
+        /// listed them, duplicates included, for linters that report
+        /// `<p id="a" id="b">`. See `ElementData::raw_attributes`. The usual
+        /// attribute map keeps one value per name either way. Off by
+        /// default.
+        pub fn keep_raw_attributes(mut self, keep: bool) -> ParserBuilder {
+            self.options.keep_raw_attributes = keep;
+            self
+        }
+
+        pub fn build(&self, input: String) -> Parser {
+            Parser {
+                pos: 0,
+                input,
+                options: self.options.clone(),
+                depth: 0,
+                node_count: 0,
+                attribute_count: 0,
+                in_foreign_content: self.options.xml,
+                in_negation: false,
+                open_elements: Vec::new(),
+                warnings: Vec::new(),
+                doctype: None,
+                reopen: Vec::new(),
+                names: HashSet::new(),
+                raw_attributes: HashMap::new(),
+            }
+        }
+
+        pub fn parse(&self, input: String) -> Result<Node, ParseError> {
+            self.build(input).parse_document()
+        }
+
+        /// Like `parse`, but also reports how much work parsing took. The
+        /// counts are kept by the parser anyway, so plain `parse` pays
+        /// nothing for this.
+        pub fn parse_with_report(&self, input: String) -> Result<(Node, ParseReport), ParseError> {
+            let start = Instant::now();
+            let mut parser = self.build(input);
+            let root = parser.parse_document()?;
+            let report = ParseReport {
+                bytes_consumed: parser.pos,
+                chars_consumed: parser.input[..parser.pos].chars().count(),
+                nodes: parser.node_count,
+                attributes: parser.attribute_count,
+                elapsed: start.elapsed(),
+            };
+            Ok((root, report))
+        }
+
+        /// Parses a whole document without wrapping several top-level nodes
+        /// in a synthetic `<html>`, keeping the doctype as well.
+        pub fn parse_document(&self, input: String) -> Result<Document, ParseError> {
+            let mut parser = self.build(input);
+            let nodes = parser.parse_top_level()?;
+            let nodes = if self.options.implied_structure {
+                vec![imply_document_structure(nodes)]
+            } else {
+                nodes
+            };
+            Ok(Document {
+                doctype: parser.doctype,
+                nodes,
+            })
+        }
+
+        /// Starts a parser that takes its input in pieces, as it arrives from
+        /// the network.
+        pub fn chunked(&self) -> ChunkedParser {
+            ChunkedParser {
+                parser: self.build(String::new()),
+                nodes: Vec::new(),
+                offset: 0,
+                stalled_at: 0,
+            }
+        }
+
+        /// Parses a stylesheet, applying the `max_selectors` and
+        /// `max_combinators` limits.
+        pub fn parse_css(&self, source: &str) -> Result<Stylesheet, ParseError> {
+            self.build(source.to_string()).parse_stylesheet()
+        }
+
+        /// Like `parse`, but also returns the problems the parser worked
+        /// around, in input order. Clean input gives no warnings.
+        pub fn parse_with_warnings(
+            &self,
+            input: String,
+        ) -> Result<(Node, Vec<Warning>), ParseError> {
+            let mut parser = self.build(input);
+            let root = parser.parse_document()?;
+            Ok((root, parser.warnings))
+        }
+
+        /// Brings `tree`, parsed by `parse` from the input before `edit`, up
+        /// to date with `new_input`, for editors that reparse on every
+        /// keystroke. Only the innermost element around the edit is parsed
+        /// again, with the rest of the input in place for its end tag rules,
+        /// and spans after the edit are shifted. When that element comes out
+        /// with another tag or extent, its ancestors are tried in turn, and
+        /// failing those the whole input is parsed. So is it in `recover`
+        /// mode, whose fix-ups depend on everything before them, and with
+        /// `strip_namespaces`, which loses the names end tags are matched
+        /// against. An error leaves `tree` as it was.
+        pub fn reparse(
+            &self,
+            tree: &mut Node,
+            edit: Edit,
+            new_input: &str,
+        ) -> Result<(), ParseError> {
+            let incremental = !self.options.recover
+                && !self.options.strip_namespaces
+                && edit.start <= edit.old_end.min(edit.new_end)
+                && edit.new_end <= new_input.len();
+            if let Some((path, element)) = incremental
+                .then(|| self.reparse_element(tree, edit, new_input))
+                .flatten()
+            {
+                shift_spans(tree, edit);
+                let mut node = tree;
+                for &index in &path {
+                    node = &mut node.children[index];
+                }
+                *node = element;
+                return Ok(());
+            }
+            *tree = self.parse(new_input.to_string())?;
+            Ok(())
+        }
+
+        /// The innermost element around `edit` that parses from `new_input`
+        /// into one with the same tag ending where the edit moved its end,
+        /// with the child indices leading to it from `tree`.
+        fn reparse_element(
+            &self,
+            tree: &Node,
+            edit: Edit,
+            new_input: &str,
+        ) -> Option<(Vec<usize>, Node)> {
+            // Elements whose span holds the edit, so one chain of ancestors.
+            // Spanless elements were made up by the parser and are looked
+            // through.
+            let mut around: Vec<(Vec<usize>, &Node)> = Vec::new();
+            let mut stack = vec![(Vec::new(), tree)];
+            while let Some((path, node)) = stack.pop() {
+                if let Some(span) = node.span {
+                    if span.start >= edit.start || span.end <= edit.old_end {
+                        continue;
+                    }
+                    around.push((path.clone(), node));
+                }
+                for (index, child) in node.children.iter().enumerate() {
+                    if child.element().is_some() {
+                        let mut path = path.clone();
+                        path.push(index);
+                        stack.push((path, child));
+                    }
+                }
+            }
+            around.sort_by_key(|(path, _)| path.len());
+            let parsed_nodes = |node: &Node| node.walk().filter(|(_, n)| n.span.is_some()).count();
+            let total = parsed_nodes(tree);
+
+            for (depth, (path, old)) in around.iter().enumerate().rev() {
+                let (Some(span), Some(old_elem)) = (old.span, old.element()) else {
+                    continue;
+                };
+                let mut parser = self.build(new_input.to_string());
+                parser.pos = span.start;
+                parser.depth = depth;
+                parser.open_elements = around[..depth]
+                    .iter()
+                    .filter_map(|(_, node)| node.element())
+                    .map(|elem| elem.tag_name().to_string())
+                    .collect();
+                parser.in_foreign_content = parser.open_elements.iter().any(|open| {
+                    FOREIGN_ROOTS
+                        .iter()
+                        .any(|root| root.eq_ignore_ascii_case(open))
+                });
+                parser.node_count = total - parsed_nodes(old);
+                let Ok(element) = parser.parse_node() else {
+                    continue;
+                };
+                let same_tag = element
+                    .element()
+                    .is_some_and(|elem| elem.tag_name().eq_ignore_ascii_case(old_elem.tag_name()));
+                if same_tag && parser.pos == edit.shift(span.end) && parser.warnings.is_empty() {
+                    return Some((path.clone(), element));
+                }
+            }
+            None
+        }
+    }
+
+    /// Moves the spans of `tree` that end after `edit` to where the edit put
+    /// them.
+    fn shift_spans(tree: &mut Node, edit: Edit) {
+        let mut stack = vec![tree];
+        while let Some(node) = stack.pop() {
+            if let Some(ref mut span) = node.span {
+                if span.end <= edit.start {
+                    continue;
+                }
+                if span.start >= edit.old_end {
+                    span.start = edit.shift(span.start);
+                }
+                if span.end >= edit.old_end {
+                    span.end = edit.shift(span.end);
+                }
+            }
+            stack.extend(node.children.iter_mut());
+        }
+    }
+
+    /// Something the parser fixed up rather than failing on, such as an
+    /// implicitly closed element in `recover` mode.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Warning {
+        pub pos: usize,
+        pub message: String,
+    }
+
+    impl fmt::Display for Warning {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{} at byte {}", self.message, self.pos)
+        }
+    }
+
+    /// A document as written: its doctype and each top-level node, such as
+    /// a leading comment, the `<html>` element and anything after it.
+    pub struct Document {
+        /// The doctype's text, `html` for `<!DOCTYPE html>`.
+        pub doctype: Option<String>,
+        pub nodes: Vec<Node>,
+    }
+
+    impl Document {
+        /// The first top-level element, normally `<html>`.
+        pub fn root_element(&self) -> Option<&Node> {
+            self.nodes.iter().find(|node| node.element().is_some())
+        }
+
+        /// The single tree `ParserBuilder::parse` would have returned: the
+        /// only top-level node, or a synthetic `html` element holding them.
+        pub fn into_node(mut self) -> Node {
+            if self.nodes.len() == 1 {
+                self.nodes.swap_remove(0)
+            } else {
+                elem("html".to_string(), HashMap::new(), self.nodes)
+            }
+        }
+    }
+
+    /// Input fed in chunks, from `ParserBuilder::chunked`. Each top-level
+    /// node is parsed as soon as the chunks holding it, and the start of the
+    /// node after it, have arrived, and only the input not yet parsed is
+    /// kept. A tag, entity or comment split between two chunks, such as
+    /// `<di` then `v>`, waits for the rest, so it parses as if it had
+    /// arrived whole. Nothing blocks: call `feed` whenever a chunk arrives
+    /// and `finish` at the end of the stream. Errors are reported by
+    /// `finish`, since more input might have fixed them.
+    pub struct ChunkedParser {
+        /// Holds the input not yet parsed into `nodes`.
+        parser: Parser,
+        nodes: Vec<Node>,
+        /// How many bytes of the stream were parsed and dropped from the
+        /// parser's input, which spans are measured past.
+        offset: usize,
+        /// The input's length when a pass last finished no node. The next
+        /// pass waits until the input has doubled, so a large unfinished
+        /// node isn't parsed again for every chunk.
+        stalled_at: usize,
+    }
+
+    impl ChunkedParser {
+        /// Adds the next chunk and parses the top-level nodes it completes.
+        /// Fails as soon as the input grows past the `max_input_size` limit,
+        /// so an oversized stream can be dropped without reading it to the
+        /// end.
+        pub fn feed(&mut self, chunk: &str) -> Result<(), ParseError> {
+            let max_input_size = self.parser.options.max_input_size;
+            if self.len() + chunk.len() > max_input_size {
+                return Err(ParseError {
+                    pos: self.len(),
+                    message: format!("input is larger than {} bytes", max_input_size),
+                });
+            }
+            self.parser.input.push_str(chunk);
+            if self.parser.input.len() < 2 * self.stalled_at {
+                return Ok(());
+            }
+            // A node is only final once the next one has parsed too: until
+            // then more input could extend its text or change which tag
+            // ends it, as `<p>a<div` becoming `<p>a<divx>` would.
+            let mut finished = self.parser.checkpoint();
+            let mut last = None;
+            loop {
+                let before = self.parser.checkpoint();
+                let Some(Ok(node)) = self.parser.parse_next_node() else {
+                    break;
+                };
+                if let Some(mut done) = last.replace(node) {
+                    self.shift(&mut done);
+                    self.nodes.push(done);
+                    finished = before;
+                }
+            }
+            self.parser.restore(finished);
+            let parsed = self.parser.pos;
+            self.stalled_at = if parsed == 0 {
+                self.parser.input.len()
+            } else {
+                0
+            };
+            self.parser.input.drain(..parsed);
+            self.parser.pos = 0;
+            self.offset += parsed;
+            Ok(())
+        }
+
+        /// How many bytes have been fed so far.
+        pub fn len(&self) -> usize {
+            self.offset + self.parser.input.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        /// Parses the rest of the input, returning the tree
+        /// `ParserBuilder::parse` would have built from everything fed.
+        pub fn finish(mut self) -> Result<Node, ParseError> {
+            let offset = self.offset;
+            let rest = self.parser.parse_top_level().map_err(|error| ParseError {
+                pos: error.pos + offset,
+                message: error.message,
+            })?;
+            for mut node in rest {
+                self.shift(&mut node);
+                self.nodes.push(node);
+            }
+            if self.parser.options.implied_structure {
+                return Ok(imply_document_structure(self.nodes));
+            }
+            Ok(Document {
+                doctype: None,
+                nodes: self.nodes,
+            }
+            .into_node())
+        }
+
+        /// Moves a node's spans from the parser's input to the stream's.
+        fn shift(&self, node: &mut Node) {
+            let edit = Edit {
+                start: 0,
+                old_end: 0,
+                new_end: self.offset,
+            };
+            shift_spans(node, edit);
+        }
+    }
+
+    /// The parser state a top-level node can change, so that
+    /// `ChunkedParser` can parse a node again once more input arrives.
+    struct Checkpoint {
+        pos: usize,
+        node_count: usize,
+        attribute_count: usize,
+        in_foreign_content: bool,
+        warnings: usize,
+        doctype: Option<String>,
+        reopen: Vec<(String, AttrMap)>,
+    }
+
+    /// Statistics from `ParserBuilder::parse_with_report`.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ParseReport {
+        pub bytes_consumed: usize,
+        pub chars_consumed: usize,
+        /// Elements, text nodes and comments, not counting a synthetic root.
+        pub nodes: usize,
+        pub attributes: usize,
+        pub elapsed: Duration,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ParseError {
+        pub pos: usize,
+        pub message: String,
+    }
+
+    impl fmt::Display for ParseError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{} at byte {}", self.message, self.pos)
+        }
+    }
+
+    impl std::error::Error for ParseError {}
+
+    type PropertyMap = HashMap<String, Value>;
+
+    /// Each property's value with the specificity and text of the selector
+    /// whose declaration set it, from `StyledNode::sourced_values`.
+    pub type SourcedPropertyMap = HashMap<String, (Value, Specificity, String)>;
+
+    /// Where each property's winning declaration came from.
+    type SourceMap = HashMap<String, (Specificity, String)>;
+
+    pub struct StyledNode<'a> {
+        node: &'a Node,
+        specified_values: PropertyMap,
+        /// Empty unless built by `style_tree_with_sources`.
+        sources: SourceMap,
+        children: Vec<StyledNode<'a>>,
+    }
+
+    /// The outer display type of a box, from the `display` property.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Display {
+        Inline,
+        Block,
+        InlineBlock,
+        ListItem,
+        Flex,
+        Grid,
+        None,
+    }
+
+    /// The slant of a font, from `font-style`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum FontStyle {
+        Normal,
+        Italic,
+        Oblique,
+    }
+
+    /// A node's font, from `StyledNode::font`.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Font {
+        pub style: FontStyle,
+        /// From 1 to 1000; `normal` is 400 and `bold` 700.
+        pub weight: u16,
+        /// Always a `Value::Length`, so `to_px` resolves it.
+        pub size: Value,
+        /// Family names in order of preference, unquoted.
+        pub family: Vec<String>,
+    }
+
+    impl<'a> StyledNode<'a> {
+        pub fn node(&self) -> &'a Node {
+            self.node
+        }
+
+        pub fn children(&self) -> &[StyledNode<'a>] {
+            &self.children
+        }
+
+        /// The specified value of a property, if it has one.
+        pub fn value(&self, name: &str) -> Option<&Value> {
+            self.specified_values.get(name)
+        }
+
+        /// The specified values of the properties a declaration set, each
+        /// with the specificity and text of the selector it came from, so a
+        /// tool can say that `color` comes from `.foo` at `(0, 1, 0)`. A
+        /// value from a `style` attribute has an empty selector and
+        /// specificity `(0, 0, 0)`. Values that were only inherited have no
+        /// declaration and are left out. Only a tree from
+        /// `style_tree_with_sources` has sources; for others this is empty.
+        pub fn sourced_values(&self) -> SourcedPropertyMap {
+            self.sources
+                .iter()
+                .filter_map(|(name, (specificity, selector))| {
+                    let value = self.specified_values.get(name)?;
+                    Some((
+                        name.clone(),
+                        (value.clone(), *specificity, selector.clone()),
+                    ))
+                })
+                .collect()
+        }
+
+        /// The specified values as declarations, `color: #ff0000; display:
+        /// block;`, sorted by property name so the output is stable.
+        pub fn to_css_string(&self) -> String {
+            let mut names: Vec<&String> = self.specified_values.keys().collect();
+            names.sort();
+            names
+                .iter()
+                .map(|&name| format!("{}: {};", name, self.specified_values[name]))
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+
+        /// Whether the node would be drawn, judging by its own computed style:
+        /// not `display: none`, not `visibility: hidden` or `collapse`, and
+        /// without the `hidden` attribute. Unlike `ElementData::is_hidden`
+        /// this sees stylesheets, but it can't see ancestors, so a walk should
+        /// skip the subtree under any node that is `display: none`.
+        pub fn is_visible(&self) -> bool {
+            let hidden_by_visibility = matches!(
+                self.value("visibility"),
+                Some(Value::Keyword(k))
+                    if k.eq_ignore_ascii_case("hidden") || k.eq_ignore_ascii_case("collapse")
+            );
+            let hidden_attribute = self.node.element().is_some_and(ElementData::is_hidden);
+            self.display() != Display::None && !hidden_by_visibility && !hidden_attribute
+        }
+
+        /// The node's `display`, defaulting to `Inline` when it's unspecified
+        /// or not a keyword this engine knows.
+        pub fn display(&self) -> Display {
+            match self.value("display") {
+                Some(Value::Keyword(k)) => match k.to_ascii_lowercase().as_str() {
+                    "block" => Display::Block,
+                    "inline-block" => Display::InlineBlock,
+                    "list-item" => Display::ListItem,
+                    "flex" => Display::Flex,
+                    "grid" => Display::Grid,
+                    "none" => Display::None,
+                    _ => Display::Inline,
+                },
+                _ => Display::Inline,
+            }
+        }
+
+        /// The node's font, from the `font-style`, `font-weight`,
+        /// `font-size` and `font-family` properties, which the `font`
+        /// shorthand sets as well. `None` without a length size and a
+        /// family.
+        pub fn font(&self) -> Option<Font> {
+            let keyword = |name: &str| match self.value(name) {
+                Some(Value::Keyword(k)) => Some(k.to_ascii_lowercase()),
+                _ => None,
+            };
+            let size = match self.value("font-size") {
+                Some(size @ Value::Length(..)) => size.clone(),
+                _ => return None,
+            };
+            // Family names keep their case.
+            let family = match self.value("font-family") {
+                Some(Value::Keyword(family)) => font_family_list(family),
+                _ => return None,
+            };
+            if family.is_empty() {
+                return None;
+            }
+            let style = match keyword("font-style").as_deref() {
+                Some("italic") => FontStyle::Italic,
+                Some("oblique") => FontStyle::Oblique,
+                _ => FontStyle::Normal,
+            };
+            let weight = keyword("font-weight")
+                .and_then(|weight| font_weight(&weight))
+                .unwrap_or(400);
+            Some(Font {
+                style,
+                weight,
+                size,
+                family,
+            })
+        }
+    }
+
+    /// The longhands a `font` shorthand such as `italic bold 12px/1.5
+    /// "Fira Sans", serif` sets: optional style and weight, then a required
+    /// size and family. Style, weight and line height it leaves out are
+    /// reset to `normal`. `None` for a shorthand this engine doesn't read,
+    /// such as one with `small-caps`, a stretch or a system font like
+    /// `caption`, or one using `var()`.
+    fn font_longhands(text: &str) -> Option<[(&'static str, Value); 5]> {
+        let mut style = "normal";
+        let mut weight = 400;
+        let mut line_height = Value::Keyword("normal".to_string());
+        let mut rest = text.trim();
+        let size = loop {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == '/')
+                .unwrap_or(rest.len());
+            let word = rest[..end].to_ascii_lowercase();
+            rest = rest[end..].trim_start();
+            match word.as_str() {
+                "normal" => {}
+                "italic" => style = "italic",
+                "oblique" => style = "oblique",
+                word => match font_weight(word) {
+                    Some(number) => weight = number,
+                    None => match parse_value_text(word)? {
+                        size @ Value::Length(..) => break size,
+                        _ => return None,
+                    },
+                },
+            }
+        };
+        if let Some(after_slash) = rest.strip_prefix('/') {
+            let after_slash = after_slash.trim_start();
+            let end = after_slash
+                .find(char::is_whitespace)
+                .unwrap_or(after_slash.len());
+            let text = &after_slash[..end];
+            line_height =
+                parse_value_text(text).unwrap_or_else(|| Value::Keyword(text.to_string()));
+            rest = &after_slash[end..];
+        }
+        if font_family_list(rest).is_empty() {
+            return None;
+        }
+        Some([
+            ("font-style", Value::Keyword(style.to_string())),
+            ("font-weight", Value::Keyword(weight.to_string())),
+            ("font-size", size),
+            ("line-height", line_height),
+            // Kept as written, since family names keep their case.
+            ("font-family", Value::Keyword(rest.trim().to_string())),
+        ])
+    }
+
+    /// `bold`, `normal` or a number from 1 to 1000 as a numeric weight.
+    fn font_weight(weight: &str) -> Option<u16> {
+        match weight {
+            "normal" => Some(400),
+            "bold" => Some(700),
+            _ => weight
+                .parse()
+                .ok()
+                .filter(|weight| (1..=1000).contains(weight)),
+        }
+    }
+
+    fn font_family_list(text: &str) -> Vec<String> {
+        text.split(',')
+            .map(|family| family.trim().trim_matches(|c| c == '"' || c == '\'').trim())
+            .filter(|family| !family.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    pub struct Stylesheet {
+        rules: Vec<Rule>,
+        at_rules: Vec<AtRule>,
+        origin: Origin,
+    }
+
+    /// An at-rule such as `@media screen { ... }` or `@import "a.css";`. The
+    /// cascade applies the rules in `@media` and `@supports` blocks as if
+    /// their conditions held; other at-rules are kept but not applied.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct AtRule {
+        pub name: String,
+        pub prelude: String,
+        /// The raw text between the braces, or `None` for statement at-rules.
+        pub block: Option<String>,
+        /// The rules inside an `@media` or `@supports` block, parsed as a
+        /// stylesheet's would be. Empty for other at-rules.
+        pub rules: Vec<Rule>,
+        /// At-rules nested in an `@media` or `@supports` block, such as an
+        /// `@supports` inside an `@media`.
+        pub at_rules: Vec<AtRule>,
+        /// How many rules of the enclosing stylesheet or block come before
+        /// this at-rule, which places it among them.
+        rules_before: usize,
+    }
+
+    /// A rule or an at-rule, for walking both in source order.
+    enum CssItem<'a> {
+        Rule(&'a Rule),
+        AtRule(&'a AtRule),
+    }
+
+    /// `rules` and `at_rules` interleaved back into source order.
+    fn in_source_order<'a>(
+        rules: &'a [Rule],
+        at_rules: &'a [AtRule],
+    ) -> impl Iterator<Item = CssItem<'a>> {
+        let mut next = 0;
+        at_rules
+            .iter()
+            .map(Some)
+            .chain(std::iter::once(None))
+            .flat_map(move |at_rule| {
+                let end = at_rule.map_or(rules.len(), |at_rule| {
+                    at_rule.rules_before.clamp(next, rules.len())
+                });
+                let before = &rules[next..end];
+                next = end;
+                before
+                    .iter()
+                    .map(CssItem::Rule)
+                    .chain(at_rule.map(CssItem::AtRule))
+            })
+    }
+
+    /// Where a stylesheet comes from. User-agent declarations lose to author
+    /// ones whatever their specificity, except that among `!important`
+    /// declarations the user agent's win.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum Origin {
+        UserAgent,
+        Author,
+    }
+
+    impl AtRule {
+        /// The URL of an `@import "a.css"` or `@import url("a.css")` rule.
+        /// Anything after the URL, such as a media query, is ignored.
+        pub fn import_url(&self) -> Option<String> {
+            if !self.name.eq_ignore_ascii_case("import") {
+                return None;
+            }
+            let prelude = self.prelude.trim();
+            let url = match prelude.get(..4) {
+                Some(function) if function.eq_ignore_ascii_case("url(") => {
+                    prelude[4..].split(')').next()?.trim()
+                }
+                _ => prelude,
+            };
+            let url = match url.chars().next()? {
+                quote @ ('"' | '\'') => url[1..].split(quote).next()?,
+                _ => url.split_whitespace().next()?,
+            };
+            Some(url.to_string())
+        }
+    }
+
+    impl Stylesheet {
+        pub fn with_origin(mut self, origin: Origin) -> Stylesheet {
+            self.origin = origin;
+            self
+        }
+
+        pub fn at_rules(&self) -> &[AtRule] {
+            &self.at_rules
+        }
+
+        /// Replaces each `@import` with the rules of the sheet it names,
+        /// fetched through `loader` and parsed, following their own imports
+        /// too. The imported rules take the `@import`'s place, which valid
+        /// CSS puts before any rule. Imports the loader can't supply, and
+        /// circular ones, are left in `at_rules`. If an imported sheet fails
+        /// to parse, the error is returned and this sheet is left unchanged.
+        pub fn load_imports(
+            &mut self,
+            loader: impl Fn(&str) -> Option<String>,
+        ) -> Result<(), ParseError> {
+            self.load_imports_from(&loader, &mut Vec::new())
+        }
+
+        /// `loading` holds the URLs being imported further up, to stop cycles.
+        fn load_imports_from(
+            &mut self,
+            loader: &dyn Fn(&str) -> Option<String>,
+            loading: &mut Vec<String>,
+        ) -> Result<(), ParseError> {
+            // Everything that can fail happens before `self` is touched, so
+            // an error leaves the sheet as it was.
+            let mut imports = Vec::with_capacity(self.at_rules.len());
+            for at_rule in &self.at_rules {
+                let source = at_rule
+                    .import_url()
+                    .filter(|url| !loading.contains(url))
+                    .and_then(|url| Some((loader(&url)?, url)));
+                let Some((source, url)) = source else {
+                    imports.push(None);
+                    continue;
+                };
+                let mut imported = parse_css(&source)?;
+                loading.push(url);
+                let result = imported.load_imports_from(loader, loading);
+                loading.pop();
+                result?;
+                imports.push(Some(imported));
+            }
+            let mut loaded = Stylesheet {
+                rules: Vec::new(),
+                at_rules: Vec::new(),
+                origin: self.origin,
+            };
+            let mut rules = std::mem::take(&mut self.rules).into_iter();
+            let mut taken = 0;
+            for (mut at_rule, imported) in
+                std::mem::take(&mut self.at_rules).into_iter().zip(imports)
+            {
+                let before = at_rule.rules_before.saturating_sub(taken);
+                loaded.rules.extend(rules.by_ref().take(before));
+                taken += before;
+                match imported {
+                    Some(imported) => loaded.append(imported),
+                    None => {
+                        at_rule.rules_before = loaded.rules.len();
+                        loaded.at_rules.push(at_rule);
+                    }
+                }
+            }
+            loaded.rules.extend(rules);
+            *self = loaded;
+            Ok(())
+        }
+
+        /// Adds `other`'s rules and at-rules after this sheet's own.
+        fn append(&mut self, other: Stylesheet) {
+            let offset = self.rules.len();
+            self.at_rules
+                .extend(other.at_rules.into_iter().map(|mut at_rule| {
+                    at_rule.rules_before += offset;
+                    at_rule
+                }));
+            self.rules.extend(other.rules);
+        }
+
+        /// What changed from this stylesheet to `other`: rules whose selector
+        /// list only one of them has, and rules whose declarations differ.
+        /// Rules are matched by selector list, so `a,b` and `a, b` are the
+        /// same rule, and several rules with one selector list count as one
+        /// with all of their declarations. Removed and changed rules come
+        /// first, in this stylesheet's order, then added ones in `other`'s.
+        pub fn diff(&self, other: &Stylesheet) -> Vec<RuleDiff> {
+            let before = self.declarations_by_selectors();
+            let after = other.declarations_by_selectors();
+            let mut diffs = Vec::new();
+            for (selectors, declarations) in &before {
+                match after.iter().find(|(other, _)| other == selectors) {
+                    None => diffs.push(RuleDiff::Removed {
+                        selectors: selectors.clone(),
+                        declarations: declarations.clone(),
+                    }),
+                    Some((_, changed)) if changed != declarations => {
+                        diffs.push(RuleDiff::Changed {
+                            selectors: selectors.clone(),
+                            before: declarations.clone(),
+                            after: changed.clone(),
+                        })
+                    }
+                    Some(_) => {}
+                }
+            }
+            for (selectors, declarations) in after {
+                if !before.iter().any(|(other, _)| *other == selectors) {
+                    diffs.push(RuleDiff::Added {
+                        selectors,
+                        declarations,
+                    });
+                }
+            }
+            diffs
+        }
+
+        /// The stylesheet as compact CSS that parses back to the same rules,
+        /// give or take these changes:
+        ///
+        /// - Within a rule, only the declaration of each property that wins
+        ///   is kept: the last `!important` one, or failing that the last.
+        /// - Rules left without declarations are dropped.
+        /// - A rule whose declarations are the same, in the same order, as
+        ///   an earlier rule's has its selectors added to that rule, as long
+        ///   as no rule in between sets any of those properties. Moving the
+        ///   selectors up past such rules can't change what wins, which it
+        ///   could if a rule in between set the same property.
+        ///
+        /// At-rules keep their place among the rules, and rules are only
+        /// merged with others between the same two at-rules. `@media` and
+        /// `@supports` blocks are minified the same way; other blocks are
+        /// kept as written.
+        pub fn minify(&self) -> String {
+            let mut css = String::new();
+            minify_block(&self.rules, &self.at_rules, &mut css);
+            css
+        }
+
+        /// Each distinct selector list with the declarations of every rule
+        /// using it, in order of first appearance.
+        fn declarations_by_selectors(&self) -> Vec<(String, Vec<String>)> {
+            let mut grouped: Vec<(String, Vec<String>)> = Vec::new();
+            for rule in &self.rules {
+                let selectors = rule
+                    .selectors
+                    .iter()
+                    .map(Selector::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let declarations = rule.declarations.iter().map(Declaration::to_string);
+                match grouped.iter_mut().find(|(other, _)| *other == selectors) {
+                    Some((_, existing)) => existing.extend(declarations),
+                    None => grouped.push((selectors, declarations.collect())),
+                }
+            }
+            grouped
+        }
+
+        /// The rules that apply to `elem` in cascade order: ascending
+        /// specificity, ties kept in source order, so the last rule wins.
+        pub fn matching_rules_for(&self, elem: &ElementData) -> Vec<(Specificity, &Rule)> {
+            let mut rules: Vec<(Specificity, &Rule)> = matching_rules(elem, None, self)
+                .into_iter()
+                .map(|(specificity, rule, _)| (specificity, rule))
+                .collect();
+            rules.sort_by_key(|&(specificity, _)| specificity);
+            rules
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Rule {
+        pub selectors: Vec<Selector>,
+        pub declarations: Vec<Declaration>,
+    }
+
+    /// Writes the rule as `a, b { color: red; margin: 1px; }`.
+    impl fmt::Display for Rule {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            for (i, selector) in self.selectors.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}", selector)?;
+            }
+            f.write_str(" {")?;
+            for declaration in &self.declarations {
+                write!(f, " {};", declaration)?;
+            }
+            f.write_str(" }")
+        }
+    }
+
+    /// Writes the at-rule as `@import "a.css";` or `@media print { ... }`,
+    /// keeping the block's text exactly.
+    impl fmt::Display for AtRule {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "@{}", self.name)?;
+            if !self.prelude.is_empty() {
+                write!(f, " {}", self.prelude)?;
+            }
+            match self.block {
+                Some(ref block) => write!(f, " {{{}}}", block),
+                None => f.write_str(";"),
+            }
+        }
+    }
+
+    /// Writes a stylesheet's or group's contents, in source order.
+    fn minify_block(rules: &[Rule], at_rules: &[AtRule], css: &mut String) {
+        let mut next = 0;
+        for at_rule in at_rules {
+            let end = at_rule.rules_before.clamp(next, rules.len());
+            minify_rules(&rules[next..end], css);
+            minify_at_rule(at_rule, css);
+            next = end;
+        }
+        minify_rules(&rules[next..], css);
+    }
+
+    /// Writes `rules` as `Stylesheet::minify` describes.
+    fn minify_rules(rules: &[Rule], css: &mut String) {
+        /// Each declaration's property name and minified text.
+        type Declarations<'a> = Vec<(&'a str, String)>;
+        // Each kept rule's selectors and declarations, minified.
+        let mut blocks: Vec<(Vec<String>, Declarations)> = Vec::new();
+        for rule in rules {
+            let winners = rule
+                .declarations
+                .iter()
+                .enumerate()
+                .filter(|&(i, declaration)| {
+                    let beaten_later = rule.declarations[i + 1..].iter().any(|later| {
+                        later.name == declaration.name
+                            && (later.important || !declaration.important)
+                    });
+                    let beaten_earlier = rule.declarations[..i].iter().any(|earlier| {
+                        earlier.name == declaration.name
+                            && earlier.important
+                            && !declaration.important
+                    });
+                    !beaten_later && !beaten_earlier
+                });
+            let declarations: Declarations = winners
+                .map(|(_, declaration)| {
+                    let important = if declaration.important {
+                        "!important"
+                    } else {
+                        ""
+                    };
+                    (
+                        declaration.name.as_str(),
+                        format!("{}:{}{}", declaration.name, declaration.raw, important),
+                    )
+                })
+                .collect();
+            if declarations.is_empty() {
+                continue;
+            }
+            let selectors: Vec<String> = rule.selectors.iter().map(minify_selector).collect();
+            let mut merge_into = None;
+            for (index, (_, earlier)) in blocks.iter().enumerate().rev() {
+                if *earlier == declarations {
+                    merge_into = Some(index);
+                    break;
+                }
+                let shares_property = earlier
+                    .iter()
+                    .any(|(name, _)| declarations.iter().any(|(other, _)| name == other));
+                if shares_property {
+                    break;
+                }
+            }
+            match merge_into {
+                Some(index) => {
+                    let merged = &mut blocks[index].0;
+                    for selector in selectors {
+                        if !merged.contains(&selector) {
+                            merged.push(selector);
+                        }
+                    }
+                }
+                None => blocks.push((selectors, declarations)),
+            }
+        }
+        for (selectors, declarations) in blocks {
+            css.push_str(&selectors.join(","));
+            css.push('{');
+            let declarations: Vec<String> =
+                declarations.into_iter().map(|(_, text)| text).collect();
+            css.push_str(&declarations.join(";"));
+            css.push('}');
+        }
+    }
+
+    fn minify_at_rule(at_rule: &AtRule, css: &mut String) {
+        css.push('@');
+        css.push_str(&at_rule.name);
+        if !at_rule.prelude.is_empty() {
+            css.push(' ');
+            css.push_str(&at_rule.prelude);
+        }
+        match at_rule.block {
+            Some(_) if is_group_at_rule(&at_rule.name) => {
+                css.push('{');
+                minify_block(&at_rule.rules, &at_rule.at_rules, css);
+                css.push('}');
+            }
+            Some(ref block) => {
+                css.push('{');
+                css.push_str(block.trim());
+                css.push('}');
+            }
+            None => css.push(';'),
+        }
+    }
+
+    /// The selector as `Display` writes it, without the spaces around
+    /// combinators other than the descendant one.
+    fn minify_selector(selector: &Selector) -> String {
+        match *selector {
+            Selector::Simple(ref simple) => simple.to_string(),
+            Selector::Complex(ref relatives, ref subject) => {
+                let mut minified = String::new();
+                for (simple, combinator) in relatives {
+                    minified.push_str(&simple.to_string());
+                    minified.push(match combinator {
+                        Combinator::Descendant => ' ',
+                        Combinator::Child => '>',
+                        Combinator::NextSibling => '+',
+                        Combinator::SubsequentSibling => '~',
+                    });
+                }
+                minified.push_str(&subject.to_string());
+                minified
+            }
+        }
+    }
+
+    /// Writes one rule or at-rule per line, in source order. The origin
+    /// isn't written.
+    impl fmt::Display for Stylesheet {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            for item in in_source_order(&self.rules, &self.at_rules) {
+                match item {
+                    CssItem::Rule(rule) => writeln!(f, "{}", rule)?,
+                    CssItem::AtRule(at_rule) => writeln!(f, "{}", at_rule)?,
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// How a rule differs between two stylesheets, as reported by
+    /// `Stylesheet::diff`. Rules are identified by their selector list, as
+    /// `Display` writes it, and declarations are given as `name: value`.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum RuleDiff {
+        Added {
+            selectors: String,
+            declarations: Vec<String>,
+        },
+        Removed {
+            selectors: String,
+            declarations: Vec<String>,
+        },
+        Changed {
+            selectors: String,
+            before: Vec<String>,
+            after: Vec<String>,
+        },
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct SimpleSelector {
+        tag_name: Option<String>,
+        /// The prefix of `svg|rect`, or `Some("")` for `|rect`, which only
+        /// matches elements in no namespace. `rect` and `*|rect` match in any
+        /// namespace, as there is no default namespace.
+        namespace: Option<String>,
+        id: Option<String>,
+        class: Vec<String>,
+        attributes: Vec<AttributeSelector>,
+        pseudo_classes: Vec<PseudoClass>,
+        /// A `::before`-style pseudo-element, lowercased. Matching ignores
+        /// it, so the selector applies to the element it's attached to.
+        pseudo_element: Option<String>,
+    }
+
+    /// `[name]`, or `[name op "value"]` comparing the attribute's value.
+    /// Names are compared exactly, like tag names.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct AttributeSelector {
+        name: String,
+        matcher: Option<(AttributeOperator, String)>,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum AttributeOperator {
+        /// `=`: exactly the value.
+        Equals,
+        /// `~=`: the value is one of the whitespace-separated words.
+        Includes,
+        /// `|=`: exactly the value, or the value followed by `-`, as
+        /// `[lang|=en]` matches `en` and `en-US`.
+        DashMatch,
+        /// `^=`: starts with the value.
+        Prefix,
+        /// `$=`: ends with the value.
+        Suffix,
+        /// `*=`: contains the value.
+        Substring,
+    }
+
+    impl AttributeOperator {
+        fn as_str(self) -> &'static str {
+            match self {
+                AttributeOperator::Equals => "=",
+                AttributeOperator::Includes => "~=",
+                AttributeOperator::DashMatch => "|=",
+                AttributeOperator::Prefix => "^=",
+                AttributeOperator::Suffix => "$=",
+                AttributeOperator::Substring => "*=",
+            }
+        }
+    }
+
+    impl AttributeSelector {
+        /// An empty value never matches `~=`, `^=`, `$=` or `*=`, and a
+        /// value with whitespace never matches `~=`.
+        fn matches(&self, elem: &ElementData) -> bool {
+            let Some(actual) = elem.get_attribute(&self.name) else {
+                return false;
+            };
+            let Some((operator, ref value)) = self.matcher else {
+                return true;
+            };
+            let value = value.as_str();
+            match operator {
+                AttributeOperator::Equals => actual == value,
+                AttributeOperator::Includes => {
+                    !value.is_empty()
+                        && !value.contains(|c: char| c.is_ascii_whitespace())
+                        && actual.split_ascii_whitespace().any(|word| word == value)
+                }
+                AttributeOperator::DashMatch => actual
+                    .strip_prefix(value)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('-')),
+                AttributeOperator::Prefix => !value.is_empty() && actual.starts_with(value),
+                AttributeOperator::Suffix => !value.is_empty() && actual.ends_with(value),
+                AttributeOperator::Substring => !value.is_empty() && actual.contains(value),
+            }
+        }
+    }
+
+    /// Pseudo-classes that can be decided from the parsed tree alone.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum PseudoClass {
+        /// `:nth-child(an+b)`; `:first-child` is `NthChild(0, 1)`.
+        NthChild(i32, i32),
+        /// `:nth-last-child(an+b)`; `:last-child` is `NthLastChild(0, 1)`.
+        NthLastChild(i32, i32),
+        /// `:nth-of-type(an+b)`, counting only siblings with the same tag
+        /// name; `:first-of-type` is `NthOfType(0, 1)`.
+        NthOfType(i32, i32),
+        /// `:nth-last-of-type(an+b)`; `:last-of-type` is
+        /// `NthLastOfType(0, 1)`.
+        NthLastOfType(i32, i32),
+        /// `:empty`: no child elements and no text, not even whitespace.
+        /// Comments don't count.
+        Empty,
+        /// `:blank`, the lenient `:empty`: text that is only whitespace
+        /// doesn't count either.
+        Blank,
+        /// `:root`: the element at the top of the tree, which has no parent.
+        /// An element matched on its own counts as a root.
+        Root,
+        /// A state read from a boolean attribute: `:checked`, `:disabled` and
+        /// `:required` are `Attribute("checked")` and so on. States that need
+        /// user interaction, such as `:hover` and `:focus`, aren't supported.
+        Attribute(String),
+        /// `:lang(en)`: the element's language, from its own `lang`
+        /// attribute or else its nearest ancestor's, is `en` or starts with
+        /// `en-`, ignoring case, as BCP 47 tags are matched by prefix. So
+        /// `en-US` and `en-GB` match but `eng` doesn't, and an empty `lang`,
+        /// meaning unknown, matches nothing.
+        Lang(String),
+        /// `:not(p.intro)`: the element doesn't match the compound selector.
+        /// As in Selectors Level 3, the argument can't hold a combinator, a
+        /// pseudo-element or another `:not()`.
+        Not(Box<SimpleSelector>),
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Declaration {
+        pub name: String,
+        /// A single length, color or keyword. Any other value, such as
+        /// `1px solid red`, is a `Keyword` holding the text as written.
+        pub value: Value,
+        /// The value exactly as written, minus surrounding whitespace and
+        /// `!important`, for properties this crate doesn't model.
+        pub raw: String,
+        pub important: bool,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    #[allow(clippy::enum_variant_names)]
+    pub enum Value {
+        Keyword(String),
+        Length(f32, Unit),
+        ColorValue(Color),
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Unit {
+        Px,
+        Em,
+        Rem,
+        Percent,
+        Pt,
+    }
+
+    /// What relative lengths are measured against when converting to pixels.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct LengthContext {
+        /// The element's font size in pixels, for `em`.
+        pub font_size: f32,
+        /// The root element's font size in pixels, for `rem`.
+        pub root_font_size: f32,
+        /// The length in pixels that `100%` refers to.
+        pub percentage_base: f32,
+    }
+
+    impl Value {
+        /// Resolves a length to pixels. Returns `None` for values that are
+        /// not lengths, such as keywords and colors.
+        pub fn to_px(&self, context: &LengthContext) -> Option<f32> {
+            match *self {
+                Value::Length(length, ref unit) => Some(match unit {
+                    Unit::Px => length,
+                    Unit::Em => length * context.font_size,
+                    Unit::Rem => length * context.root_font_size,
+                    Unit::Percent => length / 100.0 * context.percentage_base,
+                    Unit::Pt => length * 96.0 / 72.0,
+                }),
+                _ => None,
+            }
+        }
+    }
+
+    /// Writes the value as CSS: `block`, `1.5em`, `50%` or `#ff0000`. A
+    /// translucent color gets a fourth alpha pair, `#ff000080`.
+    impl fmt::Display for Value {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                Value::Keyword(ref keyword) => f.write_str(keyword),
+                Value::Length(length, ref unit) => {
+                    let unit = match unit {
+                        Unit::Px => "px",
+                        Unit::Em => "em",
+                        Unit::Rem => "rem",
+                        Unit::Percent => "%",
+                        Unit::Pt => "pt",
+                    };
+                    write!(f, "{}{}", length, unit)
+                }
+                Value::ColorValue(ref color) => {
+                    write!(f, "#{:02x}{:02x}{:02x}", color.r, color.g, color.b)?;
+                    if color.a != 255 {
+                        write!(f, "{:02x}", color.a)?;
+                    }
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Writes the declaration as `color: red` or `color: red !important`,
+    /// with the value as it was written.
+    impl fmt::Display for Declaration {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}: {}", self.name, self.raw)?;
+            if self.important {
+                f.write_str(" !important")?;
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Color {
+        pub r: u8,
+        pub g: u8,
+        pub b: u8,
+        pub a: u8,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Selector {
+        Simple(SimpleSelector),
+        /// Compound selectors joined by combinators, such as `ul > li`. Each
+        /// entry is a compound selector and the combinator that follows it;
+        /// the last selector is the subject, the element being matched.
+        Complex(Vec<(SimpleSelector, Combinator)>, SimpleSelector),
+    }
+
+    /// How two compound selectors in a complex selector relate.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Combinator {
+        /// `a b`: an ancestor.
+        Descendant,
+        /// `a > b`: the parent.
+        Child,
+        /// `a + b`: the element sibling just before.
+        NextSibling,
+        /// `a ~ b`: any earlier element sibling.
+        SubsequentSibling,
+    }
+
+    pub type Specificity = (usize, usize, usize);
+
+    /// Writes the selector in a normalized form: one space around `>`, `+`
+    /// and `~`, and shorthands such as `:first-child` where they exist.
+    /// Parsing the output gives an equivalent selector.
+    impl fmt::Display for Selector {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                Selector::Simple(ref simple) => write!(f, "{}", simple),
+                Selector::Complex(ref relatives, ref subject) => {
+                    for (simple, combinator) in relatives {
+                        let combinator = match combinator {
+                            Combinator::Descendant => " ",
+                            Combinator::Child => " > ",
+                            Combinator::NextSibling => " + ",
+                            Combinator::SubsequentSibling => " ~ ",
+                        };
+                        write!(f, "{}{}", simple, combinator)?;
+                    }
+                    write!(f, "{}", subject)
+                }
+            }
+        }
+    }
+
+    impl fmt::Display for SimpleSelector {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            if let Some(ref namespace) = self.namespace {
+                write!(f, "{}|", namespace)?;
+            }
+            match self.tag_name {
+                Some(ref tag_name) => f.write_str(tag_name)?,
+                None if self.namespace.is_some()
+                    || (self.id.is_none()
+                        && self.class.is_empty()
+                        && self.attributes.is_empty()
+                        && self.pseudo_classes.is_empty()
+                        && self.pseudo_element.is_none()) =>
+                {
+                    f.write_str("*")?
+                }
+                None => {}
+            }
+            if let Some(ref id) = self.id {
+                write!(f, "#{}", id)?;
+            }
+            for class in &self.class {
+                write!(f, ".{}", class)?;
+            }
+            for attribute in &self.attributes {
+                write!(f, "{}", attribute)?;
+            }
+            for pseudo_class in &self.pseudo_classes {
+                write!(f, "{}", pseudo_class)?;
+            }
+            if let Some(ref pseudo_element) = self.pseudo_element {
+                write!(f, "::{}", pseudo_element)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl fmt::Display for AttributeSelector {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self.matcher {
+                None => write!(f, "[{}]", self.name),
+                Some((operator, ref value)) => {
+                    write!(f, "[{}{}\"", self.name, operator.as_str())?;
+                    for c in value.chars() {
+                        match c {
+                            '"' | '\\' => write!(f, "\\{}", c)?,
+                            // A space ends the escape, so a hex digit after
+                            // it isn't taken as part of it.
+                            c if c.is_control() => write!(f, "\\{:x} ", c as u32)?,
+                            c => write!(f, "{}", c)?,
+                        }
+                    }
+                    f.write_str("\"]")
+                }
+            }
+        }
+    }
+
+    impl fmt::Display for PseudoClass {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let nth = |f: &mut fmt::Formatter, name: &str, a: i32, b: i32| {
+                write!(f, ":{}(", name)?;
+                match a {
+                    0 => return write!(f, "{})", b),
+                    1 => f.write_str("n")?,
+                    -1 => f.write_str("-n")?,
+                    a => write!(f, "{}n", a)?,
+                }
+                match b {
+                    0 => f.write_str(")"),
+                    b => write!(f, "{:+})", b),
+                }
+            };
+            match *self {
+                PseudoClass::NthChild(0, 1) => f.write_str(":first-child"),
+                PseudoClass::NthLastChild(0, 1) => f.write_str(":last-child"),
+                PseudoClass::NthChild(a, b) => nth(f, "nth-child", a, b),
+                PseudoClass::NthLastChild(a, b) => nth(f, "nth-last-child", a, b),
+                PseudoClass::NthOfType(0, 1) => f.write_str(":first-of-type"),
+                PseudoClass::NthLastOfType(0, 1) => f.write_str(":last-of-type"),
+                PseudoClass::NthOfType(a, b) => nth(f, "nth-of-type", a, b),
+                PseudoClass::NthLastOfType(a, b) => nth(f, "nth-last-of-type", a, b),
+                PseudoClass::Empty => f.write_str(":empty"),
+                PseudoClass::Blank => f.write_str(":blank"),
+                PseudoClass::Root => f.write_str(":root"),
+                PseudoClass::Attribute(ref name) => write!(f, ":{}", name),
+                PseudoClass::Lang(ref range) => write!(f, ":lang({})", range),
+                PseudoClass::Not(ref selector) => write!(f, ":not({})", selector),
+            }
+        }
+    }
+
+    impl Selector {
+        /// The compound selector the element itself must match: the last one.
+        fn subject(&self) -> &SimpleSelector {
+            match *self {
+                Selector::Simple(ref simple) | Selector::Complex(_, ref simple) => simple,
+            }
+        }
+
+        /// Parses a single selector once so it can be matched against many
+        /// elements. Use `ElementData::matches_str` for selector lists.
+        pub fn parse(source: &str) -> Result<Selector, ParseError> {
+            let mut selectors = parse_selector_list(source)?;
+            if selectors.len() != 1 {
+                return Err(ParseError {
+                    pos: 0,
+                    message: "expected a single selector, not a list".to_string(),
+                });
+            }
+            Ok(selectors.remove(0))
+        }
+
+        /// Matches the element on its own; structural pseudo-classes treat
+        /// it as an only child, and selectors with combinators never match
+        /// since there is no tree to look at.
+        pub fn matches(&self, elem: &ElementData) -> bool {
+            matches(elem, None, self)
+        }
+
+        /// Summed over every compound selector of a complex selector.
+        pub fn specificity(&self) -> Specificity {
+            match *self {
+                Selector::Simple(ref simple) => simple.specificity(),
+                Selector::Complex(ref relatives, ref subject) => relatives
+                    .iter()
+                    .map(|(simple, _)| simple.specificity())
+                    .fold(subject.specificity(), |(a, b, c), (x, y, z)| {
+                        (a + x, b + y, c + z)
+                    }),
+            }
+        }
+
+        /// The pseudo-element the selector targets, such as `before` for
+        /// `p::before`. It can only end the selector, so `p::before span`
+        /// doesn't parse.
+        pub fn pseudo_element(&self) -> Option<&str> {
+            match *self {
+                Selector::Simple(ref simple) | Selector::Complex(_, ref simple) => {
+                    simple.pseudo_element.as_deref()
+                }
+            }
+        }
+    }
+
+    /// A batch of selectors, indexed so that matching an element only tries
+    /// the selectors that could match it: those requiring its id, one of its
+    /// classes or its tag name, plus those requiring none of these.
+    pub struct SelectorSet {
+        selectors: Vec<Selector>,
+        by_id: HashMap<String, Vec<usize>>,
+        by_class: HashMap<String, Vec<usize>>,
+        by_tag: HashMap<String, Vec<usize>>,
+        /// Selectors with no id, class or tag to index by, such as `*`.
+        others: Vec<usize>,
+    }
+
+    impl SelectorSet {
+        pub fn new(selectors: Vec<Selector>) -> SelectorSet {
+            let mut set = SelectorSet {
+                selectors: Vec::new(),
+                by_id: HashMap::new(),
+                by_class: HashMap::new(),
+                by_tag: HashMap::new(),
+                others: Vec::new(),
+            };
+            for (index, selector) in selectors.iter().enumerate() {
+                let (Selector::Simple(ref subject) | Selector::Complex(_, ref subject)) = *selector;
+                let bucket = if let Some(ref id) = subject.id {
+                    set.by_id.entry(id.clone()).or_default()
+                } else if let Some(class) = subject.class.first() {
+                    set.by_class.entry(class.clone()).or_default()
+                } else if let Some(ref tag_name) = subject.tag_name {
+                    set.by_tag.entry(tag_name.clone()).or_default()
+                } else {
+                    &mut set.others
+                };
+                bucket.push(index);
+            }
+            set.selectors = selectors;
+            set
+        }
+
+        /// Parses a comma-separated selector list into a set. The selectors
+        /// are kept in the order stylesheets keep them, most specific first.
+        pub fn parse(source: &str) -> Result<SelectorSet, ParseError> {
+            Ok(SelectorSet::new(parse_selector_list(source)?))
+        }
+
+        pub fn selectors(&self) -> &[Selector] {
+            &self.selectors
+        }
+
+        /// The indices, in ascending order, of the selectors matching `elem`
+        /// on its own, as `Selector::matches` would decide.
+        pub fn matching_indices(&self, elem: &ElementData) -> Vec<usize> {
+            let mut candidates: Vec<usize> = self.others.clone();
+            let buckets = elem
+                .id()
+                .and_then(|id| self.by_id.get(id))
+                .into_iter()
+                .chain(
+                    elem.class_tokens()
+                        .filter_map(|class| self.by_class.get(class)),
+                )
+                .chain(self.by_tag.get(elem.local_name()));
+            for bucket in buckets {
+                candidates.extend(bucket);
+            }
+            candidates.sort_unstable();
+            candidates.dedup();
+            candidates.retain(|&index| matches(elem, None, &self.selectors[index]));
+            candidates
+        }
+    }
+
+    impl SimpleSelector {
+        /// Each attribute selector and pseudo-class counts as a class, except
+        /// that `:not()` counts as whatever its argument does and adds
+        /// nothing itself.
+        fn specificity(&self) -> Specificity {
+            let mut a = self.id.iter().count();
+            let mut b = self.class.len() + self.attributes.len();
+            let mut c = self.tag_name.iter().count() + self.pseudo_element.iter().count();
+            for pseudo_class in &self.pseudo_classes {
+                match *pseudo_class {
+                    PseudoClass::Not(ref selector) => {
+                        let (x, y, z) = selector.specificity();
+                        a += x;
+                        b += y;
+                        c += z;
+                    }
+                    _ => b += 1,
+                }
+            }
+
+            (a, b, c)
+        }
+    }
+
+    /// An element's place in its tree: the sibling list and index at every
+    /// level from the root down to the element itself.
+    #[derive(Clone, Copy)]
+    struct TreeContext<'a, 'p> {
+        path: &'p [(&'a [Node], usize)],
+    }
+
+    impl<'a, 'p> TreeContext<'a, 'p> {
+        fn node(&self) -> &'a Node {
+            let (siblings, index) = self.path[self.path.len() - 1];
+            &siblings[index]
+        }
+
+        /// The element's 1-based index among its element siblings, and the
+        /// number of element siblings including itself. With `same_type`,
+        /// only siblings with the same tag name, ignoring case, count.
+        fn element_position(&self, same_type: bool) -> (usize, usize) {
+            let (siblings, index) = self.path[self.path.len() - 1];
+            let tag_name = siblings[index].element().map(|elem| &elem.tag_name);
+            let counts = |node: &&Node| {
+                node.element().is_some_and(|elem| {
+                    !same_type
+                        || tag_name.is_some_and(|tag| tag.eq_ignore_ascii_case(&elem.tag_name))
+                })
+            };
+            let before = siblings[..index].iter().filter(counts).count();
+            let total = siblings.iter().filter(counts).count();
+            (before + 1, total)
+        }
+    }
+
+    /// Calls `visit` for each descendant of the last node in `path`, in
+    /// document order, with `path` extended to reach that descendant. The
+    /// inert contents of `<template>` elements are skipped unless
+    /// `include_templates` is set.
+    fn walk_descendants<'a>(
+        path: &mut Vec<(&'a [Node], usize)>,
+        include_templates: bool,
+        visit: &mut dyn FnMut(TreeContext<'a, '_>),
+    ) {
+        let (siblings, index) = path[path.len() - 1];
+        let node = &siblings[index];
+        if node.is_template() && !include_templates && path.len() > 1 {
+            return;
+        }
+        for index in 0..node.children.len() {
+            path.push((&node.children, index));
+            visit(TreeContext { path });
+            walk_descendants(path, include_templates, visit);
+            path.pop();
+        }
+    }
+
+    /// Matches `selector` against `elem`. Without a context the element is
+    /// treated as if it stood alone, i.e. as the first and last child.
+    fn matches(elem: &ElementData, context: Option<TreeContext>, selector: &Selector) -> bool {
+        match *selector {
+            Selector::Simple(ref simple) => matches_compound(elem, context, simple),
+            Selector::Complex(ref relatives, ref subject) => {
+                matches_compound(elem, context, subject)
+                    && context.is_some_and(|context| matches_relatives(relatives, context.path))
+            }
+        }
+    }
+
+    fn matches_compound(
+        elem: &ElementData,
+        context: Option<TreeContext>,
+        selector: &SimpleSelector,
+    ) -> bool {
+        matches_simple_selector(elem, selector)
+            && selector
+                .namespace
+                .as_ref()
+                .is_none_or(|namespace| element_namespace(elem, context) == namespace)
+            && selector
+                .pseudo_classes
+                .iter()
+                .all(|pseudo_class| matches_pseudo_class(elem, pseudo_class, context))
+    }
+
+    /// Whether `relatives`, read right to left, match elements related to
+    /// the one at the end of `path`. Only what `path` reaches is searched, so
+    /// a query on a subtree can't see the subtree root's ancestors.
+    fn matches_relatives(
+        relatives: &[(SimpleSelector, Combinator)],
+        path: &[(&[Node], usize)],
+    ) -> bool {
+        let Some(((selector, combinator), rest)) = relatives.split_last() else {
+            return true;
+        };
+        let matches_at = |path: &[(&[Node], usize)]| {
+            let context = TreeContext { path };
+            context
+                .node()
+                .element()
+                .is_some_and(|elem| matches_compound(elem, Some(context), selector))
+                && matches_relatives(rest, path)
+        };
+        let (siblings, index) = path[path.len() - 1];
+        match combinator {
+            Combinator::Child => path.len() > 1 && matches_at(&path[..path.len() - 1]),
+            Combinator::Descendant => (1..path.len()).rev().any(|len| matches_at(&path[..len])),
+            Combinator::NextSibling | Combinator::SubsequentSibling => {
+                let mut earlier = (0..index)
+                    .rev()
+                    .filter(|&i| siblings[i].element().is_some());
+                let mut sibling_path = path.to_vec();
+                let matches_sibling = |i: usize| {
+                    *sibling_path.last_mut().expect("paths are never empty") = (siblings, i);
+                    matches_at(&sibling_path)
+                };
+                if *combinator == Combinator::NextSibling {
+                    earlier.next().is_some_and(matches_sibling)
+                } else {
+                    earlier.any(matches_sibling)
+                }
+            }
+        }
+    }
+
+    /// Without a tree, an element is taken to be an only child, and
+    /// `:empty` and `:blank` never match since its children are unknown.
+    fn matches_pseudo_class(
+        elem: &ElementData,
+        pseudo_class: &PseudoClass,
+        context: Option<TreeContext>,
+    ) -> bool {
+        let position =
+            |same_type| context.map_or((1, 1), |context| context.element_position(same_type));
+        let is_empty = |whitespace_counts: bool| {
+            context.is_some_and(|context| {
+                context
+                    .node()
+                    .children
+                    .iter()
+                    .all(|child| match child.node_type {
+                        NodeType::Comment(_) => true,
+                        NodeType::Text(ref text) => {
+                            text.is_empty() || (!whitespace_counts && text.trim().is_empty())
+                        }
+                        NodeType::Element(_) => false,
+                    })
+            })
+        };
+        match *pseudo_class {
+            PseudoClass::NthChild(a, b) => nth_matches(a, b, position(false).0),
+            PseudoClass::NthLastChild(a, b) => {
+                let (index, count) = position(false);
+                nth_matches(a, b, count + 1 - index)
+            }
+            PseudoClass::NthOfType(a, b) => nth_matches(a, b, position(true).0),
+            PseudoClass::NthLastOfType(a, b) => {
+                let (index, count) = position(true);
+                nth_matches(a, b, count + 1 - index)
+            }
+            PseudoClass::Empty => is_empty(true),
+            PseudoClass::Blank => is_empty(false),
+            PseudoClass::Root => context.is_none_or(|context| context.path.len() == 1),
+            PseudoClass::Attribute(ref name) => elem.has_attribute(name),
+            PseudoClass::Lang(ref range) => {
+                let ancestors = context.map_or(&[][..], |context| context.path);
+                let lang = elem.get_attribute("lang").or_else(|| {
+                    ancestors
+                        .iter()
+                        .rev()
+                        .filter_map(|&(siblings, index)| siblings[index].element())
+                        .find_map(|elem| elem.get_attribute("lang"))
+                });
+                lang.is_some_and(|lang| {
+                    lang.get(..range.len())
+                        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(range))
+                        && matches!(lang.as_bytes().get(range.len()), None | Some(b'-'))
+                })
+            }
+            PseudoClass::Not(ref selector) => !matches_compound(elem, context, selector),
+        }
+    }
+
+    /// Whether `index` (1-based) equals `a*n + b` for some `n >= 0`.
+    fn nth_matches(a: i32, b: i32, index: usize) -> bool {
+        // In i64, since `b` can be as low as `i32::MIN`.
+        let (a, offset) = (i64::from(a), index as i64 - i64::from(b));
+        if a == 0 {
+            offset == 0
+        } else {
+            offset % a == 0 && offset / a >= 0
+        }
+    }
+
+    /// Parses the `an+b` microsyntax, including `odd` and `even`.
+    fn parse_nth(argument: &str) -> Option<(i32, i32)> {
+        let argument: String = argument
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        match argument.as_str() {
+            "odd" => return Some((2, 1)),
+            "even" => return Some((2, 0)),
+            _ => {}
+        }
+        match argument.split_once('n') {
+            Some((a, b)) => {
+                let a = match a {
+                    "" | "+" => 1,
+                    "-" => -1,
+                    a => a.parse().ok()?,
+                };
+                let b = match b {
+                    "" => 0,
+                    b if b.starts_with(['+', '-']) => b.parse().ok()?,
+                    _ => return None,
+                };
+                Some((a, b))
+            }
+            None => Some((0, argument.parse().ok()?)),
+        }
+    }
+
+    impl ElementData {
+        /// The tag name as parsed, such as `div` or `svg:rect`. Borrowed, so
+        /// comparing it in a loop allocates nothing.
+        pub fn tag_name(&self) -> &str {
+            &self.tag_name
+        }
+
+        pub fn id(&self) -> Option<&str> {
+            self.get_attribute("id")
+        }
+
+        pub fn get_attribute(&self, name: &str) -> Option<&str> {
+            self.attributes.get(name).map(|value| value.as_str())
+        }
+
+        /// Every attribute as the start tag wrote it, duplicates included, in
+        /// source order. Only kept with `ParserBuilder::keep_raw_attributes`;
+        /// elements built by hand or reopened by `recover` have `None`.
+        pub fn raw_attributes(&self) -> Option<&[(String, String)]> {
+            self.raw_attributes.as_deref()
+        }
+
+        /// Whether the attribute is present at all, even with an empty value
+        /// as boolean attributes like `disabled` have.
+        pub fn has_attribute(&self, name: &str) -> bool {
+            self.attributes.contains_key(name)
+        }
+
+        /// The `data-*` attributes keyed the way the DOM's `dataset` does:
+        /// prefix dropped and camelCased, so `data-user-id` is `userId`.
+        /// Names with uppercase letters are skipped, as in browsers.
+        pub fn dataset(&self) -> HashMap<String, &str> {
+            let mut dataset = HashMap::new();
+            for (name, value) in &self.attributes {
+                let Some(key) = name.strip_prefix("data-") else {
+                    continue;
+                };
+                if key.chars().any(|c| c.is_ascii_uppercase()) {
+                    continue;
+                }
+                let mut camel = String::with_capacity(key.len());
+                let mut chars = key.chars().peekable();
+                while let Some(c) = chars.next() {
+                    match chars.peek() {
+                        Some(next) if c == '-' && next.is_ascii_lowercase() => {
+                            camel.push(next.to_ascii_uppercase());
+                            chars.next();
+                        }
+                        _ => camel.push(c),
+                    }
+                }
+                dataset.insert(camel, value.as_str());
+            }
+            dataset
+        }
+
+        /// The candidates in a `srcset` attribute, as `(url, descriptor)`
+        /// pairs such as `("photo@2x.jpg", Some("2x"))` or
+        /// `("wide.jpg", Some("640w"))`. A candidate without a descriptor has
+        /// `None`, which means `1x`. As the HTML spec parses it, a URL runs
+        /// to the next whitespace, so URLs containing commas work when a
+        /// space follows them. URLs are returned as written.
+        pub fn srcset(&self) -> Vec<(String, Option<String>)> {
+            let mut candidates = Vec::new();
+            let mut rest = self.get_attribute("srcset").unwrap_or("");
+            loop {
+                rest = rest.trim_start_matches(|c: char| c == ',' || c.is_ascii_whitespace());
+                if rest.is_empty() {
+                    return candidates;
+                }
+                let url_end = rest
+                    .find(|c: char| c.is_ascii_whitespace())
+                    .unwrap_or(rest.len());
+                let url = &rest[..url_end];
+                rest = &rest[url_end..];
+                let trimmed = url.trim_end_matches(',');
+                if trimmed.len() < url.len() {
+                    // In `a.jpg, b.jpg` the comma ends the candidate.
+                    candidates.push((trimmed.to_string(), None));
+                    continue;
+                }
+                // The descriptor runs to the next comma outside parentheses.
+                let mut depth = 0usize;
+                let descriptor_end = rest
+                    .find(|c: char| {
+                        match c {
+                            '(' => depth += 1,
+                            ')' => depth = depth.saturating_sub(1),
+                            ',' => return depth == 0,
+                            _ => {}
+                        }
+                        false
+                    })
+                    .unwrap_or(rest.len());
+                let descriptor = rest[..descriptor_end].trim();
+                candidates.push((
+                    url.to_string(),
+                    (!descriptor.is_empty()).then(|| descriptor.to_string()),
+                ));
+                rest = &rest[descriptor_end..];
+            }
+        }
+
+        /// Whether the element has the `hidden` attribute. This is only the
+        /// markup; `StyledNode::is_visible` also takes CSS into account.
+        pub fn is_hidden(&self) -> bool {
+            self.has_attribute("hidden")
+        }
+
+        /// Parses `selector` (a selector list is allowed) and tests it
+        /// against this element. Prefer `Selector::parse` when matching the
+        /// same selector repeatedly.
+        pub fn matches_str(&self, selector: &str) -> Result<bool, ParseError> {
+            let selectors = parse_selector_list(selector)?;
+            Ok(selectors.iter().any(|selector| selector.matches(self)))
+        }
+
+        /// The `dir` attribute (`ltr`, `rtl` or `auto`) if set on this element.
+        pub fn dir(&self) -> Option<&str> {
+            self.get_attribute("dir")
+        }
+
+        /// The element's classes. This builds a set on every call; use
+        /// `has_class` to test for a single class.
+        pub fn classes(&self) -> HashSet<&str> {
+            self.class_tokens().collect()
+        }
+
+        /// Whether `class` is one of this element's classes. Unlike
+        /// `classes()` this allocates nothing, so selector matching uses it.
+        pub fn has_class(&self, class: &str) -> bool {
+            self.class_tokens().any(|token| token == class)
+        }
+
+        /// The tag name without any namespace prefix: `rect` for `svg:rect`.
+        fn local_name(&self) -> &str {
+            match self.tag_name.rfind(':') {
+                Some(colon) => &self.tag_name[colon + 1..],
+                None => &self.tag_name,
+            }
+        }
+
+        fn class_tokens(&self) -> impl Iterator<Item = &str> {
+            self.attributes
+                .get("class")
+                .map(String::as_str)
+                .unwrap_or("")
+                .split_ascii_whitespace()
+        }
+
+        /// Declarations from the `style` attribute. An invalid attribute is
+        /// ignored, as browsers do.
+        fn inline_style(&self) -> Vec<Declaration> {
+            match self.attributes.get("style") {
+                Some(style) => {
+                    let mut parser = Parser::new(style.clone());
+                    parser.parse_declaration_list().unwrap_or_default()
+                }
+                None => Vec::new(),
+            }
+        }
+    }
+
+    /// The namespace `svg|rect` compares with: the prefix of a tag such as
+    /// `<svg:rect>`, or else `svg` or `math` inside those elements and
+    /// `html` everywhere else. Without a context only the element itself is
+    /// looked at.
+    fn element_namespace<'e>(elem: &'e ElementData, context: Option<TreeContext>) -> &'e str {
+        if let Some(colon) = elem.tag_name.rfind(':') {
+            return &elem.tag_name[..colon];
+        }
+        let foreign_root = |elem: &ElementData| {
+            FOREIGN_ROOTS
+                .iter()
+                .copied()
+                .find(|root| elem.tag_name.eq_ignore_ascii_case(root))
+        };
+        let ancestors = context.map_or(&[][..], |context| context.path);
+        foreign_root(elem)
+            .or_else(|| {
+                ancestors
+                    .iter()
+                    .rev()
+                    .filter_map(|&(siblings, index)| siblings[index].element())
+                    .find_map(foreign_root)
+            })
+            .unwrap_or("html")
+    }
+
+    fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
+        if selector
+            .tag_name
+            .iter()
+            .any(|name| elem.local_name() != name)
+        {
+            return false;
+        }
+
+        if selector.id.iter().any(|id| elem.id() != Some(id)) {
+            return false;
+        }
+
+        if selector.class.iter().any(|class| !elem.has_class(class)) {
+            return false;
+        }
+
+        if selector
+            .attributes
+            .iter()
+            .any(|attribute| !attribute.matches(elem))
+        {
+            return false;
+        }
+
+        true
+    }
+
+    /// A rule that matched, with its most specific matching selector.
+    type MatchedRule<'a> = (Specificity, &'a Rule, &'a Selector);
+
+    fn match_rule<'a>(
+        elem: &ElementData,
+        context: Option<TreeContext>,
+        rule: &'a Rule,
+    ) -> Option<MatchedRule<'a>> {
+        rule.selectors
+            .iter()
+            .find(|selector| matches(elem, context, selector))
+            .map(|selector| (selector.specificity(), rule, selector))
+    }
+
+    fn matching_rules<'a>(
+        elem: &ElementData,
+        context: Option<TreeContext>,
+        stylesheet: &'a Stylesheet,
+    ) -> Vec<MatchedRule<'a>> {
+        let mut matched = Vec::new();
+        match_rules_in(
+            elem,
+            context,
+            &stylesheet.rules,
+            &stylesheet.at_rules,
+            &mut matched,
+        );
+        matched
+    }
+
+    /// Adds the matching rules to `matched` in source order, taking the
+    /// condition of every `@media` and `@supports` block to hold.
+    fn match_rules_in<'a>(
+        elem: &ElementData,
+        context: Option<TreeContext>,
+        rules: &'a [Rule],
+        at_rules: &'a [AtRule],
+        matched: &mut Vec<MatchedRule<'a>>,
+    ) {
+        for item in in_source_order(rules, at_rules) {
+            match item {
+                CssItem::Rule(rule) => matched.extend(match_rule(elem, context, rule)),
+                CssItem::AtRule(at_rule) => {
+                    match_rules_in(elem, context, &at_rule.rules, &at_rule.at_rules, matched)
+                }
+            }
+        }
+    }
+
+    /// Sorts declarations by cascade priority, lowest first, so that applying
+    /// them in order leaves the winning value for each property.
+    type CascadeKey = (bool, u8, Specificity);
+
+    /// The cascaded values of `elem`. With `sources`, also records the
+    /// selector behind each winning declaration there.
+    fn specified_values(
+        elem: &ElementData,
+        context: Option<TreeContext>,
+        stylesheets: &[Stylesheet],
+        mut sources: Option<&mut SourceMap>,
+    ) -> PropertyMap {
+        let inline = elem.inline_style();
+        let mut cascade: Vec<(CascadeKey, &Declaration, Option<&Selector>)> = Vec::new();
+
+        for stylesheet in stylesheets {
+            for (specificity, rule, selector) in matching_rules(elem, context, stylesheet) {
+                for declaration in &rule.declarations {
+                    // Important user-agent declarations outrank every author
+                    // one, the reverse of the normal order.
+                    let origin = match stylesheet.origin {
+                        Origin::UserAgent if declaration.important => Origin::Author as u8 + 2,
+                        origin => origin as u8,
+                    };
+                    cascade.push((
+                        (declaration.important, origin, specificity),
+                        declaration,
+                        Some(selector),
+                    ));
+                }
+            }
+        }
+        let inline_origin = Origin::Author as u8 + 1;
+        for declaration in &inline {
+            cascade.push((
+                (declaration.important, inline_origin, (0, 0, 0)),
+                declaration,
+                None,
+            ));
+        }
+
+        // The sort is stable, so ties keep source order and later sheets win.
+        cascade.sort_by_key(|&(key, _, _)| key);
+
+        let mut values = HashMap::new();
+        for ((_, _, specificity), declaration, selector) in cascade {
+            // The `font` shorthand sets its longhands where it stands in the
+            // cascade, so whichever comes later wins.
+            let longhands = match declaration.value {
+                Value::Keyword(ref text) if declaration.name == "font" => font_longhands(text),
+                _ => None,
+            };
+            let longhands = longhands.into_iter().flatten();
+            let set = std::iter::once((declaration.name.as_str(), declaration.value.clone()));
+            for (name, value) in set.chain(longhands) {
+                values.insert(name.to_string(), value);
+                if let Some(ref mut sources) = sources {
+                    let selector = selector.map_or_else(String::new, Selector::to_string);
+                    sources.insert(name.to_string(), (specificity, selector));
+                }
+            }
+        }
+        values
+    }
+
+    /// Builds the style tree by cascading `stylesheets` over every element.
+    ///
+    /// Declarations are applied in this priority order, lowest first:
+    /// user-agent sheets < author sheets (later sheets win ties) < inline
+    /// `style` attributes < `!important` declarations in author sheets <
+    /// `!important` inline ones < `!important` user-agent ones. Within each
+    /// level the more specific selector wins.
+    ///
+    /// The CSS-wide keywords `inherit`, `initial` and `unset` are resolved
+    /// against the parent element's values and each property's initial value.
+    ///
+    /// Custom properties (`--accent: #f00`) are inherited, and a value of
+    /// `var(--accent)` or `var(--accent, blue)` is replaced by the nearest
+    /// one, or by the fallback. A property whose substitution fails behaves
+    /// as `unset`, and one whose substitution isn't a single value keeps it
+    /// as written, like any declared value.
+    pub fn style_tree<'a>(root: &'a Node, stylesheets: &[Stylesheet]) -> StyledNode<'a> {
+        let mut path = vec![(std::slice::from_ref(root), 0)];
+        style_node(&mut path, stylesheets, None, false)
+    }
+
+    /// Like `style_tree`, but each node also remembers which selector set
+    /// each of its properties, for `StyledNode::sourced_values`. That costs a
+    /// string per property per element, so plain `style_tree` skips it.
+    pub fn style_tree_with_sources<'a>(
+        root: &'a Node,
+        stylesheets: &[Stylesheet],
+    ) -> StyledNode<'a> {
+        let mut path = vec![(std::slice::from_ref(root), 0)];
+        style_node(&mut path, stylesheets, None, true)
+    }
+
+    /// The cascaded values of a single element, as `style_tree` would give
+    /// them but without inheritance: `inherit` and `unset` on inherited
+    /// properties resolve to the initial value, and `var()` only sees custom
+    /// properties set on the element itself. The element is matched as if
+    /// it stood alone, so selectors with combinators, such as `ul > li`,
+    /// never match, while `:first-child` always does.
+    pub fn computed_style(elem: &ElementData, stylesheet: &Stylesheet) -> PropertyMap {
+        let values = specified_values(elem, None, std::slice::from_ref(stylesheet), None);
+        resolve_css_wide_keywords(resolve_variables(values, None), None)
+    }
+
+    /// Styles the last node of `path`, pushing each child's position onto
+    /// `path` while recursing so selectors can see siblings and ancestors.
+    fn style_node<'a>(
+        path: &mut Vec<(&'a [Node], usize)>,
+        stylesheets: &[Stylesheet],
+        parent: Option<&PropertyMap>,
+        with_sources: bool,
+    ) -> StyledNode<'a> {
+        let (siblings, index) = path[path.len() - 1];
+        let node = &siblings[index];
+        let mut sources = HashMap::new();
+        let specified_values = match node.node_type {
+            NodeType::Element(ref elem) => {
+                let context = TreeContext { path };
+                let values = specified_values(
+                    elem,
+                    Some(context),
+                    stylesheets,
+                    with_sources.then_some(&mut sources),
+                );
+                resolve_css_wide_keywords(resolve_variables(values, parent), parent)
+            }
+            NodeType::Text(_) | NodeType::Comment(_) => HashMap::new(),
+        };
+        let mut children = Vec::with_capacity(node.children.len());
+        for index in 0..node.children.len() {
+            path.push((&node.children, index));
+            children.push(style_node(
+                path,
+                stylesheets,
+                Some(&specified_values),
+                with_sources,
+            ));
+            path.pop();
+        }
+        StyledNode {
+            node,
+            specified_values,
+            sources,
+            children,
+        }
+    }
+
+    /// Properties whose `unset` means `inherit` rather than `initial`.
+    const INHERITED_PROPERTIES: &[&str] = &[
+        "color",
+        "cursor",
+        "direction",
+        "font",
+        "font-family",
+        "font-size",
+        "font-style",
+        "font-weight",
+        "letter-spacing",
+        "line-height",
+        "list-style",
+        "list-style-type",
+        "text-align",
+        "text-indent",
+        "text-transform",
+        "visibility",
+        "white-space",
+        "word-spacing",
+    ];
+
+    fn initial_value(name: &str) -> Option<Value> {
+        let keyword = |k: &str| Some(Value::Keyword(k.to_string()));
+        match name {
+            "display" => keyword("inline"),
+            "color" => Some(Value::ColorValue(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255,
+            })),
+            "background" | "background-color" => keyword("transparent"),
+            "width" | "height" => keyword("auto"),
+            "font-style" | "font-weight" | "white-space" | "line-height" => keyword("normal"),
+            "position" => keyword("static"),
+            "float" => keyword("none"),
+            "visibility" => keyword("visible"),
+            "text-align" => keyword("left"),
+            _ if name.starts_with("margin")
+                || name.starts_with("padding")
+                || (name.starts_with("border") && name.ends_with("width")) =>
+            {
+                Some(Value::Length(0.0, Unit::Px))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether an at-rule's block holds rules, as `@media` and `@supports`
+    /// blocks do.
+    fn is_group_at_rule(name: &str) -> bool {
+        ["media", "supports"]
+            .iter()
+            .any(|group| group.eq_ignore_ascii_case(name))
+    }
+
+    /// How deeply `@media` and `@supports` blocks may nest. Each level keeps
+    /// a copy of its block's text, so every level of nesting stores the
+    /// innermost rules once more.
+    const MAX_GROUP_DEPTH: usize = 32;
+
+    /// How many `var()` references may be followed to resolve one value, which
+    /// also stops custom properties that refer to each other.
+    const MAX_VAR_DEPTH: usize = 32;
+
+    /// Inherits the parent's custom properties and substitutes every `var()`.
+    fn resolve_variables(mut values: PropertyMap, parent: Option<&PropertyMap>) -> PropertyMap {
+        for (name, value) in parent.into_iter().flatten() {
+            if name.starts_with("--") && !values.contains_key(name) {
+                values.insert(name.clone(), value.clone());
+            }
+        }
+        let substituted: Vec<(String, Option<Value>)> = values
+            .iter()
+            .filter_map(|(name, value)| match value {
+                Value::Keyword(k) if is_var_reference(k) => {
+                    let text = substitute_var(k, &values, 0);
+                    let value = if name.starts_with("--") {
+                        text.map(Value::Keyword)
+                    } else if is_text_property(name) {
+                        let unset = Value::Keyword("unset".to_string());
+                        Some(text.map_or(unset, Value::Keyword))
+                    } else {
+                        // Like a declared value, text that isn't a single
+                        // value is kept as it is.
+                        let unset = Value::Keyword("unset".to_string());
+                        Some(text.map_or(unset, |text| {
+                            parse_value_text(&text)
+                                .unwrap_or_else(|| Value::Keyword(text.trim().to_string()))
+                        }))
+                    };
+                    Some((name.clone(), value))
+                }
+                _ => None,
+            })
+            .collect();
+        for (name, value) in substituted {
+            match value {
+                Some(value) => values.insert(name, value),
+                None => values.remove(&name),
+            };
+        }
+        values
+    }
+
+    /// Properties whose values are kept as text rather than parsed as one
+    /// value: custom properties, the `font` shorthand, and `font-family`
+    /// and `font-weight`, whose lists, quoted names and unitless numbers
+    /// the value parser doesn't take.
+    fn is_text_property(name: &str) -> bool {
+        name.starts_with("--") || matches!(name, "font" | "font-family" | "font-weight")
+    }
+
+    fn is_var_reference(value: &str) -> bool {
+        value
+            .get(..4)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case("var("))
+    }
+
+    /// The text `var(--name, fallback)` stands for, looked up in `values`.
+    fn substitute_var(reference: &str, values: &PropertyMap, depth: usize) -> Option<String> {
+        if depth > MAX_VAR_DEPTH {
+            return None;
+        }
+        let inner = reference.trim().get(4..)?.strip_suffix(')')?;
+        let (name, fallback) = match inner.split_once(',') {
+            Some((name, fallback)) => (name.trim(), Some(fallback.trim())),
+            None => (inner.trim(), None),
+        };
+        let resolve = |text: &str| {
+            if is_var_reference(text) {
+                substitute_var(text, values, depth + 1)
+            } else {
+                Some(text.to_string())
+            }
+        };
+        match values.get(name) {
+            Some(Value::Keyword(text)) if !text.is_empty() => resolve(text),
+            _ => resolve(fallback?),
+        }
+    }
+
+    /// Parses a whole string as a single value.
+    fn parse_value_text(text: &str) -> Option<Value> {
+        let mut parser = Parser::new(text.trim().to_string());
+        let value = parser.parse_value().ok()?;
+        parser.eof().then_some(value)
+    }
+
+    /// Replaces `inherit`, `initial` and `unset` with concrete values. A
+    /// property left without a value (no parent value and no known initial
+    /// value) is dropped, as if it had never been declared.
+    fn resolve_css_wide_keywords(values: PropertyMap, parent: Option<&PropertyMap>) -> PropertyMap {
+        let inherit = |name: &str| {
+            parent
+                .and_then(|parent| parent.get(name).cloned())
+                .or_else(|| initial_value(name))
+        };
+        values
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let resolved = match value {
+                    Value::Keyword(ref k) if k.eq_ignore_ascii_case("inherit") => inherit(&name),
+                    Value::Keyword(ref k) if k.eq_ignore_ascii_case("initial") => {
+                        initial_value(&name)
+                    }
+                    Value::Keyword(ref k) if k.eq_ignore_ascii_case("unset") => {
+                        if INHERITED_PROPERTIES.contains(&name.as_str()) {
+                            inherit(&name)
+                        } else {
+                            initial_value(&name)
+                        }
+                    }
+                    value => Some(value),
+                };
+                resolved.map(|value| (name, value))
+            })
+            .collect()
+    }
+
+    impl Parser {
+        pub fn new(input: String) -> Parser {
+            ParserBuilder::new().build(input)
+        }
+
+        /// Parses the whole input, wrapping multiple top-level nodes in a
+        /// synthetic `<html>` element.
+        fn parse_document(&mut self) -> Result<Node, ParseError> {
+            let nodes = self.parse_top_level()?;
+            if self.options.implied_structure {
+                return Ok(imply_document_structure(nodes));
+            }
+            Ok(Document {
+                doctype: None,
+                nodes,
+            }
+            .into_node())
+        }
+
+        /// Parses all of the input as a list of sibling nodes.
+        fn parse_top_level(&mut self) -> Result<Vec<Node>, ParseError> {
+            let nodes = self.parse_nodes()?;
+            if !self.eof() {
+                return Err(self.error("unexpected closing tag".to_string()));
+            }
+            Ok(nodes)
+        }
+
+        /// Parses the input as the content of a `context_tag` element. See
+        /// `parse_fragment_in_context`.
+        fn parse_in_context(&mut self, context_tag: &str) -> Result<Vec<Node>, ParseError> {
+            let is_tag = |tags: &[&str]| tags.iter().any(|t| t.eq_ignore_ascii_case(context_tag));
+            let raw_text = is_tag(RAW_TEXT_ELEMENTS);
+            if raw_text || is_tag(RCDATA_ELEMENTS) {
+                let start = self.pos;
+                self.pos = self.input.len();
+                let content = &self.input[start..];
+                if content.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let mut node = if raw_text {
+                    text(content.to_string())
+                } else {
+                    text(decode_entities(content, &self.options.custom_entities))
+                };
+                node.span = Some(Span {
+                    start,
+                    end: self.pos,
+                });
+                self.count_node()?;
+                return Ok(vec![node]);
+            }
+            self.in_foreign_content = is_tag(FOREIGN_ROOTS);
+            self.parse_top_level()
+        }
+
+        /// The part of the input that has not been consumed yet.
+        pub fn remaining(&self) -> &str {
+            &self.input[self.pos..]
+        }
+
+        /// The state between two top-level nodes, for `restore`.
+        fn checkpoint(&self) -> Checkpoint {
+            Checkpoint {
+                pos: self.pos,
+                node_count: self.node_count,
+                attribute_count: self.attribute_count,
+                in_foreign_content: self.in_foreign_content,
+                warnings: self.warnings.len(),
+                doctype: self.doctype.clone(),
+                reopen: self.reopen.clone(),
+            }
+        }
+
+        /// Undoes everything parsed since `checkpoint`, including whatever
+        /// a failed parse left half done.
+        fn restore(&mut self, checkpoint: Checkpoint) {
+            self.pos = checkpoint.pos;
+            self.node_count = checkpoint.node_count;
+            self.attribute_count = checkpoint.attribute_count;
+            self.in_foreign_content = checkpoint.in_foreign_content;
+            self.warnings.truncate(checkpoint.warnings);
+            self.doctype = checkpoint.doctype;
+            self.reopen = checkpoint.reopen;
+            self.depth = 0;
+            self.open_elements.clear();
+            self.raw_attributes.clear();
+        }
+
+        /// Parses the next sibling node, or returns `None` once the input is
+        /// exhausted or a closing tag is reached. Doctypes before a top-level
+        /// node are skipped.
+        pub fn parse_next_node(&mut self) -> Option<Result<Node, ParseError>> {
+            if self.input.len() > self.options.max_input_size {
+                return Some(Err(self.input_too_large()));
+            }
+            self.consume_whitespace();
+            if self.depth == 0 {
+                self.skip_doctypes();
+            }
+            if self.options.recover {
+                self.skip_stray_closing_tags();
+            }
+            if self.eof() || self.starts_with("</") || self.closes_current_element() {
+                return None;
+            }
+            if self.options.lossy && self.node_count >= self.options.max_nodes {
+                self.stop_at_node_limit();
+                return None;
+            }
+            if self.reopens_formatting() {
+                return Some(self.reopen_formatting_element());
+            }
+            Some(self.parse_node())
+        }
+
+        /// Ends a lossy parse that has reached `max_nodes`. Kept apart from
+        /// `parse_next_node`, which is on the stack once per nesting level.
+        fn stop_at_node_limit(&mut self) {
+            let message = format!("document has more than {} nodes", self.options.max_nodes);
+            self.warn(self.pos, message);
+            self.pos = self.input.len();
+        }
+
+        fn input_too_large(&self) -> ParseError {
+            ParseError {
+                pos: 0,
+                message: format!("input is larger than {} bytes", self.options.max_input_size),
+            }
+        }
+
+        /// Skips `<!doctype ...>`, keeping the first one seen. Only done
+        /// between top-level nodes; inside an element a doctype is misplaced
+        /// and is kept as a bogus comment.
+        fn skip_doctypes(&mut self) {
+            while self.starts_with_ignore_case("<!doctype") {
+                self.pos += "<!doctype".len();
+                let doctype = self.consume_while(|c| c != '>');
+                self.doctype
+                    .get_or_insert_with(|| doctype.trim().to_string());
+                if !self.eof() {
+                    self.consume_char();
+                }
+                self.consume_whitespace();
+            }
+        }
+
+        /// Starts again the innermost formatting element that misnested tags
+        /// closed early, parsing its new content.
+        fn reopen_formatting_element(&mut self) -> Result<Node, ParseError> {
+            let (tag_name, attrs) = self.reopen.pop().expect("checked by reopens_formatting");
+            let start = self.pos;
+            self.warn(
+                start,
+                format!("reopened <{}> after misnested tags", tag_name),
+            );
+            self.count_node()?;
+            self.parse_element_content(tag_name, attrs, start, false)
+        }
+
+        /// Skips closing tags that match no open element, for `recover`,
+        /// warning about each one that doesn't cancel a pending reopen.
+        fn skip_stray_closing_tags(&mut self) {
+            while self.starts_with("</") && !self.closes_open_element() {
+                let start = self.pos;
+                self.consume_while(|c| c != '>');
+                if !self.eof() {
+                    self.consume_char();
+                }
+                let tag = &self.input[start..self.pos];
+                // `</i>` after `<b><i>x</b>` ends the `<i>` for good.
+                let name = tag[2..]
+                    .split(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, ':' | '-' | '_')))
+                    .next()
+                    .unwrap_or("");
+                let cancelled = self
+                    .reopen
+                    .iter()
+                    .rposition(|(open, _)| open.eq_ignore_ascii_case(name));
+                if let Some(cancelled) = cancelled {
+                    self.reopen.remove(cancelled);
+                } else {
+                    self.warn(start, format!("ignored stray closing tag {}", tag));
+                }
+                self.consume_whitespace();
+            }
+        }
+
+        /// Whether a formatting element closed by misnested tags should be
+        /// reopened here. Like browsers, that happens before text or an inline
+        /// element, so `<p><b>x</p><p>y` puts the new `<b>` inside the second
+        /// paragraph.
+        fn reopens_formatting(&self) -> bool {
+            if self.reopen.is_empty() {
+                return false;
+            }
+            let Some(rest) = self.remaining().strip_prefix('<') else {
+                return true;
+            };
+            let name = rest
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .next()
+                .unwrap_or("");
+            INLINE_ELEMENTS
+                .iter()
+                .any(|tag| tag.eq_ignore_ascii_case(name))
+        }
+
+        /// Whether the start tag here implicitly ends the innermost open
+        /// element, as `<li>` ends an `<li>`. Foreign content has no such
+        /// rules.
+        fn closes_current_element(&self) -> bool {
+            if self.in_foreign_content {
+                return false;
+            }
+            let Some(closers) = self
+                .open_elements
+                .last()
+                .and_then(|open| optional_end_tag_closers(open))
+            else {
+                return false;
+            };
+            let next = self.start_tag_name();
+            closers
+                .iter()
+                .any(|closer| closer.eq_ignore_ascii_case(&next))
+        }
+
+        /// Whether the closing tag at `pos` belongs to an element that is
+        /// still open.
+        fn closes_open_element(&self) -> bool {
+            self.open_elements
+                .iter()
+                .any(|open| self.is_closing_tag_at(self.pos, open))
+        }
+
+        fn warn(&mut self, pos: usize, message: String) {
+            self.warnings.push(Warning { pos, message });
+        }
+
+        fn error(&self, message: String) -> ParseError {
+            ParseError {
+                pos: self.pos,
+                message,
+            }
+        }
+
+        fn expect_char(&mut self, expected: char) -> Result<(), ParseError> {
+            if self.eof() {
+                return Err(self.error(format!("expected '{}' but reached end of input", expected)));
+            }
+            let start = self.pos;
+            let c = self.consume_char();
+            if c != expected {
+                return Err(ParseError {
+                    pos: start,
+                    message: format!("expected '{}' but found '{}'", expected, c),
+                });
+            }
+            Ok(())
+        }
+
+        fn next_char(&self) -> char {
+            self.input[self.pos..].chars().next().unwrap()
+        }
+
+        fn starts_with(&self, s: &str) -> bool {
+            self.input[self.pos..].starts_with(s)
+        }
+
+        fn starts_with_ignore_case(&self, s: &str) -> bool {
+            self.input.as_bytes()[self.pos..]
+                .get(..s.len())
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case(s.as_bytes()))
+        }
+
+        fn eof(&self) -> bool {
+            self.pos >= self.input.len()
+        }
+
+        fn consume_char(&mut self) -> char {
+            let cur_char = self.next_char();
+            // Advance by the char's encoded width so `pos` stays on a char
+            // boundary even when the last char of the input is multi-byte.
+            self.pos += cur_char.len_utf8();
+            cur_char
+        }
+
+        fn consume_while<T>(&mut self, test: T) -> String
+        where
+            T: Fn(char) -> bool,
+        {
+            let mut result = String::new();
+            while !self.eof() && test(self.next_char()) {
+                result.push(self.consume_char());
+            }
+
+            result
+        }
+
+        /// HTML and CSS whitespace is ASCII only, so a non-breaking space is
+        /// kept as content.
+        fn consume_whitespace(&mut self) {
+            self.consume_while(|c| c.is_ascii_whitespace());
+        }
+
+        /// Also used for attribute names. Hyphens allow custom elements such
+        /// as `<my-widget>` and attributes such as `data-id`.
+        fn parse_tag_name(&mut self) -> String {
+            self.consume_while(|c| matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | ':' | '-' | '_'))
+        }
+
+        fn count_node(&mut self) -> Result<(), ParseError> {
+            self.node_count += 1;
+            if self.node_count > self.options.max_nodes {
+                return Err(self.error(format!(
+                    "document has more than {} nodes",
+                    self.options.max_nodes
+                )));
+            }
+            Ok(())
+        }
+
+        fn parse_node(&mut self) -> Result<Node, ParseError> {
+            self.count_node()?;
+            let start = self.pos;
+            let at_element = self.next_char() == '<'
+                && !self.starts_with("<!")
+                && !self.starts_with("<?")
+                && !self.at_literal_less_than();
+            let mut node = if at_element {
+                self.parse_element()
+            } else {
+                self.parse_leaf()
+            }?;
+            node.span = Some(Span {
+                start,
+                end: self.pos,
+            });
+            Ok(node)
+        }
+
+        /// Parses a node that can't have children: text, a comment or CDATA.
+        /// Elements are parsed apart, keeping this off the stack while their
+        /// children are parsed.
+        fn parse_leaf(&mut self) -> Result<Node, ParseError> {
+            if self.starts_with("<!--") {
+                self.parse_comment()
+            } else if self.starts_with("<![CDATA[") {
+                self.parse_cdata()
+            } else if self.starts_with("<!") || self.starts_with("<?") {
+                Ok(self.parse_bogus_comment())
+            } else {
+                Ok(self.parse_text())
+            }
+        }
+
+        fn parse_comment(&mut self) -> Result<Node, ParseError> {
+            let start = self.pos;
+            self.pos += "<!--".len();
+            match self.remaining().find("-->") {
+                Some(end) => {
+                    let data = self.remaining()[..end].to_string();
+                    self.pos += end + "-->".len();
+                    Ok(comment(data))
+                }
+                None if self.options.lossy => {
+                    self.warn(start, "unterminated comment".to_string());
+                    let data = self.remaining().to_string();
+                    self.pos = self.input.len();
+                    Ok(comment(data))
+                }
+                None => Err(ParseError {
+                    pos: start,
+                    message: "unterminated comment".to_string(),
+                }),
+            }
+        }
+
+        /// `<![CDATA[...]]>` becomes a text node, taken literally.
+        fn parse_cdata(&mut self) -> Result<Node, ParseError> {
+            let start = self.pos;
+            self.pos += "<![CDATA[".len();
+            match self.remaining().find("]]>") {
+                Some(end) => {
+                    let data = self.remaining()[..end].to_string();
+                    self.pos += end + "]]>".len();
+                    Ok(text(data))
+                }
+                None if self.options.lossy => {
+                    self.warn(start, "unterminated CDATA section".to_string());
+                    let data = self.remaining().to_string();
+                    self.pos = self.input.len();
+                    Ok(text(data))
+                }
+                None => Err(ParseError {
+                    pos: start,
+                    message: "unterminated CDATA section".to_string(),
+                }),
+            }
+        }
+
+        /// Recovers from `<!...>` and `<?...>` constructs that aren't
+        /// comments, doctypes or CDATA the way browsers do: everything up to
+        /// the next `>` becomes a comment. A `<?` is kept in the comment's text.
+        fn parse_bogus_comment(&mut self) -> Node {
+            let start = self.pos;
+            self.consume_char();
+            if self.next_char() == '!' {
+                self.consume_char();
+            }
+            let data = self.consume_while(|c| c != '>');
+            if !self.eof() {
+                self.consume_char();
+            }
+            // In XML, `<?xml version="1.0"?>` and other processing
+            // instructions are fine.
+            if !(self.options.xml && data.starts_with('?')) {
+                self.warn(start, "treated bogus markup as a comment".to_string());
+            }
+            comment(data)
+        }
+
+        fn parse_text(&mut self) -> Node {
+            let start = self.pos;
+            loop {
+                self.consume_while(|c| c != '<');
+                if !self.at_literal_less_than() {
+                    break;
+                }
+                self.warn(self.pos, "treated '<' as text".to_string());
+                self.consume_char();
+            }
+            if self.options.xml {
+                self.check_xml_references(start, self.pos);
+            }
+            let data = decode_entities(&self.input[start..self.pos], &self.options.custom_entities);
+            let in_pre = self
+                .open_elements
+                .iter()
+                .any(|open| open.eq_ignore_ascii_case("pre"));
+            if !self.options.collapse_whitespace || in_pre {
+                return text(data);
+            }
+            let mut collapsed = String::with_capacity(data.len());
+            for c in data.chars() {
+                // Only ASCII whitespace collapses; U+00A0 is content.
+                if !c.is_ascii_whitespace() {
+                    collapsed.push(c);
+                } else if !collapsed.ends_with(' ') {
+                    collapsed.push(' ');
+                }
+            }
+            text(collapsed)
+        }
+
+        /// In `recover` mode, a `<` that can't start a tag, comment or end
+        /// tag, as in `5 < 10`, is text, as it is in browsers.
+        fn at_literal_less_than(&self) -> bool {
+            self.options.recover
+                && self.remaining().strip_prefix('<').is_some_and(|rest| {
+                    !rest.starts_with(|c: char| {
+                        c.is_ascii_alphabetic() || matches!(c, '/' | '!' | '?')
+                    })
+                })
+        }
+
+        fn parse_element(&mut self) -> Result<Node, ParseError> {
+            let (tag_name, attrs, tag_start, self_closing) = self.parse_start_tag()?;
+            self.parse_element_content(tag_name, attrs, tag_start, self_closing)
+        }
+
+        /// Parses a start tag into its name, attributes, the position of the
+        /// name, and whether it ends with `/>`.
+        fn parse_start_tag(&mut self) -> Result<(String, AttrMap, usize, bool), ParseError> {
+            self.expect_char('<')?;
+            let tag_start = self.pos;
+            let tag_name = self.parse_tag_name();
+            if tag_name.is_empty() {
+                return Err(self.error("expected a tag name".to_string()));
+            }
+            if !tag_name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                return Err(ParseError {
+                    pos: tag_start,
+                    message: format!("invalid tag name '{}'", tag_name),
+                });
+            }
+            let (attrs, raw_attributes) = self.parse_attributes()?;
+            if let Some(raw_attributes) = raw_attributes {
+                self.raw_attributes.insert(tag_start, raw_attributes);
+            }
+            let self_closing = self.starts_with("/>");
+            if self_closing {
+                self.consume_char();
+            }
+            if self.options.lossy && self.eof() {
+                self.warn(tag_start - 1, "unterminated start tag".to_string());
+            } else {
+                self.expect_char('>')?;
+            }
+            Ok((tag_name, attrs, tag_start, self_closing))
+        }
+
+        /// Parses what follows an element's start tag: its children and its
+        /// closing tag, if it has them.
+        fn parse_element_content(
+            &mut self,
+            tag_name: String,
+            attrs: AttrMap,
+            tag_start: usize,
+            self_closing: bool,
+        ) -> Result<Node, ParseError> {
+            let is_tag = |tags: &[&str]| tags.iter().any(|t| t.eq_ignore_ascii_case(&tag_name));
+            let foreign = self.in_foreign_content || is_tag(FOREIGN_ROOTS);
+            if self.parse_childless(&tag_name, foreign, self_closing) {
+                return Ok(self.finish_element(tag_name, attrs, tag_start, Vec::new()));
+            }
+            let children = if self.options.xml {
+                self.parse_children(&tag_name, foreign, tag_start)
+            } else if is_tag(RAW_TEXT_ELEMENTS) {
+                self.parse_raw_text(&tag_name, false, tag_start)
+            } else if is_tag(RCDATA_ELEMENTS) {
+                self.parse_raw_text(&tag_name, true, tag_start)
+            } else {
+                self.parse_children(&tag_name, foreign, tag_start)
+            }?;
+            self.parse_end_tag(tag_name, attrs, tag_start, children)
+        }
+
+        /// Whether the element just started has no content or end tag, as
+        /// with void elements and self-closed foreign elements.
+        fn parse_childless(&mut self, tag_name: &str, foreign: bool, self_closing: bool) -> bool {
+            // In HTML the `/` of `<div/>` means nothing; only void elements
+            // and foreign elements can end without a closing tag.
+            if foreign {
+                return self_closing;
+            }
+            let is_void = self
+                .options
+                .void_elements
+                .iter()
+                .any(|void| void.eq_ignore_ascii_case(tag_name));
+            if is_void {
+                // Tolerate an explicit `</br>` straight after `<br>`.
+                let close_tag = format!("</{}>", tag_name);
+                if self.starts_with_ignore_case(&close_tag) {
+                    self.pos += close_tag.len();
+                }
+            }
+            is_void
+        }
+
+        /// Parses the children of an ordinary element, up to its end tag.
+        fn parse_children(
+            &mut self,
+            tag_name: &str,
+            foreign: bool,
+            tag_start: usize,
+        ) -> Result<Vec<Node>, ParseError> {
+            if self.depth >= self.options.max_depth {
+                return self.too_deep(tag_start);
+            }
+            self.depth += 1;
+            self.open_elements.push(tag_name.to_string());
+            let was_foreign = std::mem::replace(&mut self.in_foreign_content, foreign);
+            let children = self.parse_nodes();
+            self.in_foreign_content = was_foreign;
+            self.open_elements.pop();
+            self.depth -= 1;
+            children
+        }
+
+        /// Fails on an element nested past `max_depth`, or in lossy mode
+        /// gives it no children, leaving its content to be parsed as its
+        /// siblings. Kept apart from `parse_children` to keep its frame small.
+        fn too_deep(&mut self, tag_start: usize) -> Result<Vec<Node>, ParseError> {
+            let message = format!("elements nested more than {} deep", self.options.max_depth);
+            if !self.options.lossy {
+                return Err(ParseError {
+                    pos: tag_start,
+                    message,
+                });
+            }
+            self.warn(tag_start, message);
+            Ok(Vec::new())
+        }
+
+        /// Consumes the end tag of an element whose children have been
+        /// parsed, or works out that it was left out, and builds the element.
+        /// Kept apart from `parse_element_content` so its locals aren't on
+        /// the stack while children are parsed recursively.
+        fn parse_end_tag(
+            &mut self,
+            tag_name: String,
+            attrs: AttrMap,
+            tag_start: usize,
+            children: Vec<Node>,
+        ) -> Result<Node, ParseError> {
+            let close_pos = self.pos;
+            let omitted_end_tag = !self.in_foreign_content
+                && optional_end_tag_closers(&tag_name).is_some()
+                && !self.is_closing_tag_at(close_pos, &tag_name)
+                && (!self.starts_with("</") || self.closes_open_element());
+            if omitted_end_tag {
+                return Ok(self.finish_element(tag_name, attrs, tag_start, children));
+            }
+            if self.options.recover {
+                if self.eof() {
+                    self.warn(
+                        tag_start,
+                        format!("unclosed <{}> closed at end of input", tag_name),
+                    );
+                    return Ok(self.finish_element(tag_name, attrs, tag_start, children));
+                }
+                if !self.is_closing_tag_at(close_pos, &tag_name) {
+                    // The closing tag belongs to an ancestor; leave it for them.
+                    self.warn(close_pos, format!("implicitly closed <{}>", tag_name));
+                    let formatting = FORMATTING_ELEMENTS
+                        .iter()
+                        .any(|t| t.eq_ignore_ascii_case(&tag_name));
+                    if formatting {
+                        self.reopen.push((tag_name.clone(), attrs.clone()));
+                    }
+                    return Ok(self.finish_element(tag_name, attrs, tag_start, children));
+                }
+                self.consume_while(|c| c != '>');
+                if !self.eof() {
+                    self.consume_char();
+                }
+                return Ok(self.finish_element(tag_name, attrs, tag_start, children));
+            }
+            if self.eof() {
+                let mut message = format!("unclosed <{}> at end of input", tag_name);
+                if !self.open_elements.is_empty() {
+                    let open: Vec<String> = self
+                        .open_elements
+                        .iter()
+                        .map(|open| format!("<{}>", open))
+                        .collect();
+                    message.push_str(&format!(", inside {}", open.join(" ")));
+                }
+                return Err(ParseError {
+                    pos: tag_start,
+                    message,
+                });
+            }
+            self.expect_char('<')?;
+            self.expect_char('/')?;
+            let close_name = self.parse_tag_name();
+            // HTML tag names ignore case, so `<DIV>` can end with `</div>`.
+            let matches_close = if self.options.xml {
+                close_name == tag_name
+            } else {
+                close_name.eq_ignore_ascii_case(&tag_name)
+            };
+            if !matches_close {
+                return Err(ParseError {
+                    pos: close_pos,
+                    message: format!(
+                        "expected closing tag for <{}> but found </{}>",
+                        tag_name, close_name
+                    ),
+                });
+            }
+            self.expect_char('>')?;
+            Ok(self.finish_element(tag_name, attrs, tag_start, children))
+        }
+
+        /// Builds the element whose start tag's name is at `tag_start`,
+        /// normalizing its tag name as configured.
+        fn finish_element(
+            &mut self,
+            tag_name: String,
+            attrs: AttrMap,
+            tag_start: usize,
+            children: Vec<Node>,
+        ) -> Node {
+            let tag_name = match tag_name.rfind(':') {
+                Some(colon) if self.options.strip_namespaces => tag_name[colon + 1..].to_string(),
+                _ => tag_name,
+            };
+            let tag_name = if self.options.lowercase_tags && is_html_tag(&tag_name) {
+                tag_name.to_ascii_lowercase()
+            } else {
+                tag_name
+            };
+            let mut node = elem(self.intern(tag_name), attrs, children);
+            if let NodeType::Element(ref mut elem) = node.node_type {
+                elem.raw_attributes = self
+                    .raw_attributes
+                    .remove(&tag_start)
+                    .map(Vec::into_boxed_slice);
+            }
+            node
+        }
+
+        /// The shared copy of `name` if `intern_names` is set, or else a new
+        /// one.
+        fn intern(&mut self, name: String) -> Rc<str> {
+            if !self.options.intern_names {
+                return name.into();
+            }
+            if let Some(interned) = self.names.get(name.as_str()) {
+                return Rc::clone(interned);
+            }
+            let interned: Rc<str> = name.into();
+            self.names.insert(Rc::clone(&interned));
+            interned
+        }
+
+        /// Reads the content of a raw text or RCDATA element, which is text
+        /// up to the element's closing tag: `<title>a < b</title>` holds no
+        /// child elements. Only RCDATA (`decode`) has its entities decoded.
+        fn parse_raw_text(
+            &mut self,
+            tag_name: &str,
+            decode: bool,
+            start: usize,
+        ) -> Result<Vec<Node>, ParseError> {
+            let end = match self.find_closing_tag(tag_name) {
+                Some(end) => end,
+                None if self.options.recover => self.input.len(),
+                None => {
+                    return Err(ParseError {
+                        pos: start,
+                        message: format!("unterminated <{}> element", tag_name),
+                    })
+                }
+            };
+            let content = &self.input[self.pos..end];
+            if content.is_empty() {
+                return Ok(Vec::new());
+            }
+            let mut child = if decode {
+                text(decode_entities(content, &self.options.custom_entities))
+            } else {
+                text(content.to_string())
+            };
+            child.span = Some(Span {
+                start: self.pos,
+                end,
+            });
+            self.pos = end;
+            Ok(vec![child])
+        }
+
+        /// Finds the next `</tag_name` (any case) that really closes the tag,
+        /// so `</scripts` doesn't end a `<script>`.
+        fn find_closing_tag(&self, tag_name: &str) -> Option<usize> {
+            (self.pos..self.input.len()).find(|&i| self.is_closing_tag_at(i, tag_name))
+        }
+
+        /// Whether `</tag_name` (any case) starts at byte `i`, not followed
+        /// by more of a longer name.
+        fn is_closing_tag_at(&self, i: usize, tag_name: &str) -> bool {
+            let Some(rest) = self.input.as_bytes()[i..].strip_prefix(b"</") else {
+                return false;
+            };
+            rest.get(..tag_name.len())
+                .is_some_and(|name| name.eq_ignore_ascii_case(tag_name.as_bytes()))
+                && !rest
+                    .get(tag_name.len())
+                    .is_some_and(|&b| b.is_ascii_alphanumeric() || matches!(b, b':' | b'-' | b'_'))
+        }
+
+        fn parse_attr(&mut self) -> Result<(String, String), ParseError> {
+            let name = self.parse_tag_name();
+            if name.is_empty() && self.options.lossy {
+                // An empty name tells the caller there's no attribute.
+                self.warn(
+                    self.pos,
+                    format!("ignored '{}' in a start tag", self.next_char()),
+                );
+                self.consume_char();
+                return Ok((name, String::new()));
+            }
+            if name.is_empty() {
+                return Err(self.error("expected an attribute name".to_string()));
+            }
+            // `name = "value"` is as valid as `name="value"`.
+            self.consume_whitespace();
+            if self.eof() || self.next_char() != '=' {
+                // A boolean attribute such as `disabled` has the empty value.
+                if self.options.xml {
+                    self.warn(self.pos, format!("attribute '{}' has no value", name));
+                }
+                return Ok((name, String::new()));
+            }
+            self.consume_char();
+            self.consume_whitespace();
+            let value = self.parse_attr_value()?;
+            Ok((name, value))
+        }
+
+        fn parse_attr_value(&mut self) -> Result<String, ParseError> {
+            let quoted = !self.eof() && matches!(self.next_char(), '"' | '\'');
+            if !quoted && self.options.lossy {
+                // Unquoted, as browsers allow: up to whitespace or `>`.
+                self.warn(self.pos, "unquoted attribute value".to_string());
+                let value = self.consume_while(|c| !c.is_ascii_whitespace() && c != '>');
+                return Ok(decode_entities(&value, &self.options.custom_entities));
+            }
+            if self.eof() {
+                return Err(self.error("expected a quoted attribute value".to_string()));
+            }
+            let open_quote = self.next_char();
+            if !quoted {
+                return Err(self.error(format!(
+                    "expected a quoted attribute value but found '{}'",
+                    open_quote
+                )));
+            }
+            let start = self.pos;
+            self.consume_char();
+            // The other quote character is ordinary text here: "it's fine".
+            let value = self.consume_while(|c| c != open_quote);
+            if self.eof() && self.options.lossy {
+                self.warn(start, "unterminated attribute value".to_string());
+                return Ok(decode_entities(&value, &self.options.custom_entities));
+            }
+            if self.eof() {
+                return Err(ParseError {
+                    pos: start,
+                    message: "unterminated attribute value".to_string(),
+                });
+            }
+            self.consume_char();
+            if self.options.xml {
+                self.check_xml_references(start + 1, self.pos - 1);
+            }
+            Ok(decode_entities(&value, &self.options.custom_entities))
+        }
+
+        /// Warns about each `&` in the input from `start` to `end` that isn't
+        /// a character reference XML defines: one of its five named entities,
+        /// a custom entity or a numeric reference.
+        fn check_xml_references(&mut self, start: usize, end: usize) {
+            let mut problems = Vec::new();
+            let raw = &self.input[start..end];
+            for (amp, _) in raw.match_indices('&') {
+                let name = reference_name(&raw[amp + 1..]);
+                let defined = name.is_some_and(|name| {
+                    ["amp", "lt", "gt", "quot", "apos"].contains(&name)
+                        || self.options.custom_entities.contains_key(name)
+                        || numeric_reference(name)
+                            .is_some_and(|code| code != 0 && char::from_u32(code).is_some())
+                });
+                if !defined {
+                    let message = match name {
+                        Some(name) => format!("undefined entity '&{};'", name),
+                        None => "'&' that doesn't start a character reference".to_string(),
+                    };
+                    problems.push(Warning {
+                        pos: start + amp,
+                        message,
+                    });
+                }
+            }
+            self.warnings.extend(problems);
+        }
+
+        /// The attributes of a start tag, and with `keep_raw_attributes` the
+        /// same list as written, duplicates included.
+        #[allow(clippy::type_complexity)]
+        fn parse_attributes(
+            &mut self,
+        ) -> Result<(AttrMap, Option<Vec<(String, String)>>), ParseError> {
+            let mut attributes = HashMap::new();
+            let mut raw = self.options.keep_raw_attributes.then(Vec::new);
+            // Whether attributes past `max_attributes` have been dropped.
+            let mut dropped = false;
+
+            loop {
+                self.consume_whitespace();
+
+                if self.eof() || self.next_char() == '>' || self.starts_with("/>") {
+                    break;
+                }
+
+                let too_many = attributes.len() >= self.options.max_attributes;
+                if too_many && !self.options.lossy {
+                    return Err(self.error(format!(
+                        "element has more than {} attributes",
+                        self.options.max_attributes
+                    )));
+                }
+                let start = self.pos;
+                let (name, value) = self.parse_attr()?;
+                if name.is_empty() {
+                    continue;
+                }
+                if too_many {
+                    if !dropped {
+                        let message = format!(
+                            "element has more than {} attributes",
+                            self.options.max_attributes
+                        );
+                        self.warn(start, message);
+                        dropped = true;
+                    }
+                    continue;
+                }
+                if self.options.xml && attributes.contains_key(name.as_str()) {
+                    self.warn(start, format!("duplicate attribute '{}'", name));
+                }
+                self.attribute_count += 1;
+                if let Some(ref mut raw) = raw {
+                    raw.push((name.clone(), value.clone()));
+                }
+                attributes.insert(self.intern(name), value);
+            }
+            Ok((attributes, raw))
+        }
+
+        fn parse_nodes(&mut self) -> Result<Vec<Node>, ParseError> {
+            let mut nodes = Vec::new();
+            while let Some(node) = self.parse_next_node() {
+                // Not `?`: this frame is on the stack once per nesting level,
+                // and unoptimized builds give each `?` stack space of its own.
+                match node {
+                    Ok(node) => nodes.push(node),
+                    Err(err) => return Err(err),
+                }
+            }
+            Ok(nodes)
+        }
+
+        /// Parses the head of a document and stops where the body starts,
+        /// leaving the rest of the input unread. See `parse_head_only`.
+        fn parse_head(&mut self) -> Result<Node, ParseError> {
+            let mut head_attrs = HashMap::new();
+            let mut children = Vec::new();
+            loop {
+                self.consume_whitespace();
+                if self.starts_with_ignore_case("<!doctype") {
+                    self.consume_while(|c| c != '>');
+                    if !self.eof() {
+                        self.consume_char();
+                    }
+                    continue;
+                }
+                if self.starts_with("<!--") {
+                    children.push(self.parse_node()?);
+                    continue;
+                }
+                let tag_name = self.start_tag_name();
+                let is_tag = |tag: &str| tag.eq_ignore_ascii_case(&tag_name);
+                if is_tag("html") || is_tag("head") {
+                    self.consume_char();
+                    self.parse_tag_name();
+                    let (attrs, _) = self.parse_attributes()?;
+                    if self.starts_with("/>") {
+                        self.consume_char();
+                    }
+                    self.expect_char('>')?;
+                    if is_tag("head") {
+                        for (name, value) in attrs {
+                            head_attrs.entry(name).or_insert(value);
+                        }
+                    }
+                } else if HEAD_ELEMENTS.iter().any(|tag| is_tag(tag)) {
+                    children.push(self.parse_node()?);
+                } else {
+                    break;
+                }
+            }
+            Ok(elem("head".to_string(), head_attrs, children))
+        }
+
+        /// The name of the start tag at the current position, or an empty
+        /// string if there is none.
+        fn start_tag_name(&self) -> String {
+            match self.remaining().strip_prefix('<') {
+                Some(rest) if rest.starts_with(|c: char| c.is_ascii_alphabetic()) => rest
+                    .split(
+                        |c: char| !matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | ':' | '-' | '_'),
+                    )
+                    .next()
+                    .unwrap_or("")
+                    .to_string(),
+                _ => String::new(),
+            }
+        }
+
+        fn parse_simple_selector(&mut self) -> Result<SimpleSelector, ParseError> {
+            let start = self.pos;
+            let mut selector = SimpleSelector {
+                tag_name: None,
+                namespace: None,
+                id: None,
+                class: Vec::new(),
+                attributes: Vec::new(),
+                pseudo_classes: Vec::new(),
+                pseudo_element: None,
+            };
+            // Where the pseudo-element ended, as nothing may follow it.
+            let mut pseudo_element_end = None;
+
+            while !self.eof() {
+                match self.next_char() {
+                    '#' => {
+                        self.consume_char();
+                        selector.id = Some(self.parse_selector_name("an id")?);
+                    }
+                    '.' => {
+                        self.consume_char();
+                        selector
+                            .class
+                            .push(self.parse_selector_name("a class name")?);
+                    }
+                    '[' => {
+                        self.consume_char();
+                        selector.attributes.push(self.parse_attribute_selector()?);
+                    }
+                    ':' if self.starts_with("::") || self.at_legacy_pseudo_element() => {
+                        self.consume_while(|c| c == ':');
+                        let name = self.parse_selector_name("a pseudo-element name")?;
+                        selector.pseudo_element = Some(name.to_ascii_lowercase());
+                        pseudo_element_end.get_or_insert(self.pos);
+                    }
+                    ':' => {
+                        self.consume_char();
+                        selector.pseudo_classes.push(self.parse_pseudo_class()?);
+                    }
+                    '|' if self.pos == start && !self.starts_with("|=") => {
+                        self.parse_namespace_separator()?;
+                        selector.namespace = Some(String::new());
+                    }
+                    '*' => {
+                        let at_start = self.pos == start;
+                        self.consume_char();
+                        if at_start && self.starts_with("|") && !self.starts_with("|=") {
+                            self.parse_namespace_separator()?;
+                        }
+                    }
+                    c if valid_identifier_char(c) => {
+                        let at_start = self.pos == start;
+                        let name = self.parse_identifier();
+                        if at_start && self.starts_with("|") && !self.starts_with("|=") {
+                            self.parse_namespace_separator()?;
+                            selector.namespace = Some(name);
+                        } else {
+                            selector.tag_name = Some(name);
+                        }
+                    }
+                    _ => break,
+                }
+            }
+            if let Some(end) = pseudo_element_end.filter(|&end| end < self.pos) {
+                return Err(ParseError {
+                    pos: end,
+                    message: "a pseudo-element must come last in a selector".to_string(),
+                });
+            }
+            Ok(selector)
+        }
+
+        /// The rest of `[name]` or `[name op value]` after the `[`. The value
+        /// is a quoted string or an identifier, either with CSS escapes.
+        fn parse_attribute_selector(&mut self) -> Result<AttributeSelector, ParseError> {
+            self.consume_whitespace();
+            let name = self.parse_selector_name("an attribute name")?;
+            self.consume_whitespace();
+            let operators = [
+                AttributeOperator::Equals,
+                AttributeOperator::Includes,
+                AttributeOperator::DashMatch,
+                AttributeOperator::Prefix,
+                AttributeOperator::Suffix,
+                AttributeOperator::Substring,
+            ];
+            let operator = operators
+                .into_iter()
+                .find(|operator| self.starts_with(operator.as_str()));
+            let matcher = match operator {
+                Some(operator) => {
+                    self.pos += operator.as_str().len();
+                    self.consume_whitespace();
+                    Some((operator, self.parse_attribute_value()?))
+                }
+                None => None,
+            };
+            self.consume_whitespace();
+            self.expect_char(']')?;
+            Ok(AttributeSelector { name, matcher })
+        }
+
+        fn parse_attribute_value(&mut self) -> Result<String, ParseError> {
+            let start = self.pos;
+            let mut value = String::new();
+            let Some(quote @ ('"' | '\'')) = self.remaining().chars().next() else {
+                while !self.eof() {
+                    if valid_identifier_char(self.next_char()) {
+                        value.push(self.consume_char());
+                    } else if self.at_escape() {
+                        self.consume_char();
+                        value.push(self.parse_escape());
+                    } else {
+                        break;
+                    }
+                }
+                if value.is_empty() {
+                    return self.parse_selector_name("an attribute value");
+                }
+                return Ok(value);
+            };
+            self.consume_char();
+            loop {
+                if self.eof() {
+                    return Err(ParseError {
+                        pos: start,
+                        message: "unterminated string in attribute selector".to_string(),
+                    });
+                }
+                match self.consume_char() {
+                    c if c == quote => return Ok(value),
+                    '\\' if self.eof() => {}
+                    // An escaped newline continues the string on the next line.
+                    '\\' if self.starts_with("\r\n") => self.pos += 2,
+                    '\\' if matches!(self.next_char(), '\n' | '\r' | '\x0c') => {
+                        self.consume_char();
+                    }
+                    '\\' => value.push(self.parse_escape()),
+                    c => value.push(c),
+                }
+            }
+        }
+
+        /// Whether a CSS escape starts here: a `\` not followed by a newline.
+        fn at_escape(&self) -> bool {
+            let mut chars = self.remaining().chars();
+            chars.next() == Some('\\')
+                && chars
+                    .next()
+                    .is_some_and(|c| !matches!(c, '\n' | '\r' | '\x0c'))
+        }
+
+        /// The character of a CSS escape whose `\` has been consumed: up to
+        /// six hex digits and one optional space, as in `\31 23` for `123`,
+        /// or any other character standing for itself, as in `\"`.
+        fn parse_escape(&mut self) -> char {
+            let digits = self
+                .remaining()
+                .chars()
+                .take(6)
+                .take_while(char::is_ascii_hexdigit)
+                .count();
+            if digits == 0 {
+                return self.consume_char();
+            }
+            let code =
+                u32::from_str_radix(&self.input[self.pos..self.pos + digits], 16).unwrap_or(0);
+            self.pos += digits;
+            if self.starts_with("\r\n") {
+                self.pos += 2;
+            } else if !self.eof() && matches!(self.next_char(), ' ' | '\t' | '\n' | '\r' | '\x0c') {
+                self.consume_char();
+            }
+            // NUL, surrogates and numbers past Unicode are replaced.
+            char::from_u32(code)
+                .filter(|&c| c != '\0')
+                .unwrap_or(char::REPLACEMENT_CHARACTER)
+        }
+
+        /// Consumes the `|` of `svg|rect`, which must be followed by a type
+        /// selector.
+        fn parse_namespace_separator(&mut self) -> Result<(), ParseError> {
+            self.consume_char();
+            if self.eof() || !(self.next_char() == '*' || valid_identifier_char(self.next_char())) {
+                return Err(self.error("expected a type selector after '|'".to_string()));
+            }
+            Ok(())
+        }
+
+        /// The identifier after `#` or `.`, which can't be empty: `div..foo`
+        /// is an error rather than a match on an empty class.
+        fn parse_selector_name(&mut self, what: &str) -> Result<String, ParseError> {
+            let name = self.parse_identifier();
+            if name.is_empty() {
+                let found = if self.eof() {
+                    "end of input".to_string()
+                } else {
+                    format!("'{}'", self.next_char())
+                };
+                return Err(self.error(format!("expected {} but found {}", what, found)));
+            }
+            Ok(name)
+        }
+
+        /// CSS 2 wrote four pseudo-elements with one colon, and `:before`
+        /// still means `::before`.
+        fn at_legacy_pseudo_element(&self) -> bool {
+            let name = self.remaining()[1..]
+                .split(|c| !valid_identifier_char(c))
+                .next()
+                .unwrap_or("");
+            ["after", "before", "first-letter", "first-line"]
+                .iter()
+                .any(|legacy| legacy.eq_ignore_ascii_case(name))
+        }
+
+        fn parse_pseudo_class(&mut self) -> Result<PseudoClass, ParseError> {
+            let start = self.pos;
+            let name = self.parse_identifier().to_ascii_lowercase();
+            if name == "not" && self.starts_with("(") {
+                return self.parse_negation(start);
+            }
+            let argument = if self.starts_with("(") {
+                self.consume_char();
+                let argument = self.consume_while(|c| c != ')');
+                self.expect_char(')')?;
+                Some(argument)
+            } else {
+                None
+            };
+            let nth = |constructor: fn(i32, i32) -> PseudoClass, argument: &str| {
+                parse_nth(argument)
+                    .map(|(a, b)| constructor(a, b))
+                    .ok_or_else(|| ParseError {
+                        pos: start,
+                        message: format!("invalid argument '{}' for :{}", argument, name),
+                    })
+            };
+            match (name.as_str(), argument.as_deref()) {
+                ("first-child", None) => Ok(PseudoClass::NthChild(0, 1)),
+                ("last-child", None) => Ok(PseudoClass::NthLastChild(0, 1)),
+                ("first-of-type", None) => Ok(PseudoClass::NthOfType(0, 1)),
+                ("last-of-type", None) => Ok(PseudoClass::NthLastOfType(0, 1)),
+                ("empty", None) => Ok(PseudoClass::Empty),
+                ("blank", None) => Ok(PseudoClass::Blank),
+                ("root", None) => Ok(PseudoClass::Root),
+                ("checked" | "disabled" | "required", None) => {
+                    Ok(PseudoClass::Attribute(name.clone()))
+                }
+                ("lang", Some(argument)) => {
+                    let range = argument.trim().trim_matches(|c| c == '"' || c == '\'');
+                    if range.is_empty() {
+                        return Err(ParseError {
+                            pos: start,
+                            message: "expected a language for :lang".to_string(),
+                        });
+                    }
+                    Ok(PseudoClass::Lang(range.to_string()))
+                }
+                ("nth-child", Some(argument)) => nth(PseudoClass::NthChild, argument),
+                ("nth-last-child", Some(argument)) => nth(PseudoClass::NthLastChild, argument),
+                ("nth-of-type", Some(argument)) => nth(PseudoClass::NthOfType, argument),
+                ("nth-last-of-type", Some(argument)) => nth(PseudoClass::NthLastOfType, argument),
+                _ => Err(ParseError {
+                    pos: start,
+                    message: format!("unsupported pseudo-class ':{}'", name),
+                }),
+            }
+        }
+
+        /// The parenthesized argument of `:not`, whose name starts at `start`.
+        fn parse_negation(&mut self, start: usize) -> Result<PseudoClass, ParseError> {
+            if self.in_negation {
+                return Err(ParseError {
+                    pos: start,
+                    message: ":not() can't be nested".to_string(),
+                });
+            }
+            self.consume_char();
+            self.consume_whitespace();
+            let argument_start = self.pos;
+            self.in_negation = true;
+            let selector = self.parse_simple_selector();
+            self.in_negation = false;
+            let selector = selector?;
+            if self.pos == argument_start {
+                return Err(self.error("expected a selector inside :not()".to_string()));
+            }
+            if selector.pseudo_element.is_some() {
+                return Err(ParseError {
+                    pos: argument_start,
+                    message: "a pseudo-element can't be used inside :not()".to_string(),
+                });
+            }
+            self.consume_whitespace();
+            self.expect_char(')')?;
+            Ok(PseudoClass::Not(Box::new(selector)))
+        }
+
+        fn parse_rule(&mut self) -> Result<Rule, ParseError> {
+            Ok(Rule {
+                selectors: self.parse_selectors()?,
+                declarations: self.parse_declarations()?,
+            })
+        }
+
+        fn parse_selectors(&mut self) -> Result<Vec<Selector>, ParseError> {
+            let mut selectors = Vec::new();
+            loop {
+                if selectors.len() >= self.options.max_selectors {
+                    return Err(self.error(format!(
+                        "selector list has more than {} selectors",
+                        self.options.max_selectors
+                    )));
+                }
+                selectors.push(self.parse_complex_selector()?);
+
+                self.consume_whitespace();
+                if self.eof() {
+                    break;
+                }
+                match self.next_char() {
+                    ',' => {
+                        self.consume_char();
+                        self.consume_whitespace();
+                    }
+                    '{' => break,
+                    c => {
+                        return Err(
+                            self.error(format!("unexpected character '{}' in selector list", c))
+                        )
+                    }
+                }
+            }
+
+            selectors.sort_by_key(|s| std::cmp::Reverse(s.specificity()));
+
+            Ok(selectors)
+        }
+
+        /// A compound selector, or several joined by combinators. Whitespace
+        /// alone between two compound selectors is the descendant combinator.
+        fn parse_complex_selector(&mut self) -> Result<Selector, ParseError> {
+            let mut relatives = Vec::new();
+            let mut subject = self.parse_simple_selector()?;
+            loop {
+                let before_whitespace = self.pos;
+                self.consume_whitespace();
+                let combinator = match self.remaining().chars().next() {
+                    Some('>') => Some(Combinator::Child),
+                    Some('+') => Some(Combinator::NextSibling),
+                    Some('~') => Some(Combinator::SubsequentSibling),
+                    None | Some(',' | '{') => None,
+                    Some(_) if self.pos > before_whitespace => Some(Combinator::Descendant),
+                    Some(_) => None,
+                };
+                let Some(combinator) = combinator else {
+                    break;
+                };
+                if subject.pseudo_element.is_some() {
+                    return Err(ParseError {
+                        pos: before_whitespace,
+                        message: "a pseudo-element must come last in a selector".to_string(),
+                    });
+                }
+                if relatives.len() >= self.options.max_combinators {
+                    return Err(self.error(format!(
+                        "selector has more than {} combinators",
+                        self.options.max_combinators
+                    )));
+                }
+                if combinator != Combinator::Descendant {
+                    self.consume_char();
+                    self.consume_whitespace();
+                }
+                let start = self.pos;
+                let next = self.parse_simple_selector()?;
+                if self.pos == start {
+                    return Err(self.error("expected a selector after a combinator".to_string()));
+                }
+                relatives.push((std::mem::replace(&mut subject, next), combinator));
+            }
+            if relatives.is_empty() {
+                Ok(Selector::Simple(subject))
+            } else {
+                Ok(Selector::Complex(relatives, subject))
+            }
+        }
+
+        fn parse_identifier(&mut self) -> String {
+            self.consume_while(valid_identifier_char)
+        }
+
+        fn parse_stylesheet(&mut self) -> Result<Stylesheet, ParseError> {
+            let mut stylesheet = Stylesheet {
+                rules: Vec::new(),
+                at_rules: Vec::new(),
+                origin: Origin::Author,
+            };
+            loop {
+                self.consume_whitespace();
+                if self.eof() {
+                    break;
+                }
+                if self.starts_with("@") {
+                    let mut at_rule = self.parse_at_rule()?;
+                    at_rule.rules_before = stylesheet.rules.len();
+                    stylesheet.at_rules.push(at_rule);
+                } else {
+                    stylesheet.rules.push(self.parse_rule()?);
+                }
+            }
+            Ok(stylesheet)
+        }
+
+        /// Parses `@name prelude;` or `@name prelude { ... }` without
+        /// interpreting it, so unsupported at-rules don't stop the rest of the
+        /// stylesheet from parsing.
+        fn parse_at_rule(&mut self) -> Result<AtRule, ParseError> {
+            let start = self.pos;
+            self.expect_char('@')?;
+            let name = self.parse_identifier();
+            let prelude_start = self.pos;
+            while !self.eof() && !matches!(self.next_char(), '{' | ';') {
+                if let quote @ ('"' | '\'') = self.consume_char() {
+                    self.skip_quoted(quote);
+                }
+            }
+            let prelude = self.input[prelude_start..self.pos].trim().to_string();
+            if self.eof() {
+                return Err(ParseError {
+                    pos: start,
+                    message: format!("unterminated @{} rule", name),
+                });
+            }
+            let block_start = self.pos;
+            let block = if self.next_char() == ';' {
+                self.consume_char();
+                None
+            } else {
+                Some(self.consume_block()?)
+            };
+            let (rules, at_rules) = match block {
+                Some(ref block) if is_group_at_rule(&name) => {
+                    self.parse_group_rules(block, block_start + 1)?
+                }
+                _ => (Vec::new(), Vec::new()),
+            };
+            Ok(AtRule {
+                name,
+                prelude,
+                block,
+                rules,
+                at_rules,
+                rules_before: 0,
+            })
+        }
+
+        /// Parses the body of an `@media` or `@supports` block, which starts
+        /// at byte `offset` of the input, as a stylesheet of its own.
+        fn parse_group_rules(
+            &self,
+            block: &str,
+            offset: usize,
+        ) -> Result<(Vec<Rule>, Vec<AtRule>), ParseError> {
+            if self.depth >= MAX_GROUP_DEPTH {
+                return Err(ParseError {
+                    pos: offset,
+                    message: format!("at-rules nested more than {} deep", MAX_GROUP_DEPTH),
+                });
+            }
+            let mut parser = ParserBuilder {
+                options: self.options.clone(),
+            }
+            .build(block.to_string());
+            parser.depth = self.depth + 1;
+            match parser.parse_stylesheet() {
+                Ok(stylesheet) => Ok((stylesheet.rules, stylesheet.at_rules)),
+                Err(error) => Err(ParseError {
+                    pos: error.pos + offset,
+                    message: error.message,
+                }),
+            }
+        }
+
+        /// Consumes a `{ ... }` block, including any nested blocks and quoted
+        /// strings, and returns the text between the outer braces.
+        fn consume_block(&mut self) -> Result<String, ParseError> {
+            let start = self.pos;
+            self.expect_char('{')?;
+            let content_start = self.pos;
+            let mut depth = 1;
+            while !self.eof() {
+                match self.consume_char() {
+                    '{' => depth += 1,
+                    '}' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Ok(self.input[content_start..self.pos - 1].to_string());
+                        }
+                    }
+                    quote @ ('"' | '\'') => self.skip_quoted(quote),
+                    _ => {}
+                }
+            }
+            Err(ParseError {
+                pos: start,
+                message: "unterminated block".to_string(),
+            })
+        }
+
+        /// Skips the rest of a quoted string whose opening quote was consumed.
+        fn skip_quoted(&mut self, quote: char) {
+            self.consume_while(|c| c != quote);
+            if !self.eof() {
+                self.consume_char();
+            }
+        }
+
+        fn parse_declarations(&mut self) -> Result<Vec<Declaration>, ParseError> {
+            self.expect_char('{')?;
+            let declarations = self.parse_declaration_list()?;
+            self.expect_char('}')?;
+            Ok(declarations)
+        }
+
+        /// Parses declarations up to a closing `}` or the end of input, which
+        /// lets the same code handle rule bodies and `style` attributes.
+        fn parse_declaration_list(&mut self) -> Result<Vec<Declaration>, ParseError> {
+            let mut declarations = Vec::new();
+            loop {
+                self.consume_whitespace();
+                if self.eof() || self.next_char() == '}' {
+                    break;
+                }
+                declarations.push(self.parse_declaration()?);
+            }
+            Ok(declarations)
+        }
+
+        fn parse_declaration(&mut self) -> Result<Declaration, ParseError> {
+            let name = self.parse_identifier();
+            if name.is_empty() {
+                return Err(self.error("expected a property name".to_string()));
+            }
+            self.consume_whitespace();
+            self.expect_char(':')?;
+            self.consume_whitespace();
+            let value_start = self.pos;
+            // Custom properties (`--main-color`) hold arbitrary text, kept as
+            // a keyword until something uses them. So does `var()`, which is
+            // only substituted during the cascade, and the font properties
+            // that take numbers, strings and lists, which `StyledNode::font`
+            // reads.
+            let typed = if is_text_property(&name) || self.starts_with_ignore_case("var(") {
+                None
+            } else {
+                self.parse_value().ok().filter(|_| {
+                    self.consume_whitespace();
+                    self.eof() || matches!(self.next_char(), ';' | '}' | '!')
+                })
+            };
+            let value = match typed {
+                Some(value) => value,
+                // Anything that isn't a single value, like `1px solid red`,
+                // `1.5` or `rgb(0, 0, 0)`, is kept as written.
+                None => {
+                    self.pos = value_start;
+                    self.skip_raw_value();
+                    Value::Keyword(self.input[value_start..self.pos].trim().to_string())
+                }
+            };
+            let raw = self.input[value_start..self.pos].trim().to_string();
+            if raw.is_empty() && !is_text_property(&name) {
+                return Err(ParseError {
+                    pos: value_start,
+                    message: "expected a value".to_string(),
+                });
+            }
+            self.consume_whitespace();
+            let important = self.starts_with("!");
+            if important {
+                self.consume_char();
+                self.consume_whitespace();
+                if !self.parse_identifier().eq_ignore_ascii_case("important") {
+                    return Err(self.error("expected 'important' after '!'".to_string()));
+                }
+                self.consume_whitespace();
+            }
+            // The last declaration in a block may omit its semicolon.
+            if !self.eof() && self.next_char() != '}' {
+                self.expect_char(';')?;
+            }
+
+            Ok(Declaration {
+                name,
+                value,
+                raw,
+                important,
+            })
+        }
+
+        /// Skips a value up to the `;`, `}` or `!important` that ends it,
+        /// stepping over strings and bracketed blocks.
+        fn skip_raw_value(&mut self) {
+            let mut depth = 0usize;
+            while !self.eof() {
+                match self.next_char() {
+                    ';' | '}' | '!' if depth == 0 => break,
+                    '(' | '[' | '{' => depth += 1,
+                    ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                    quote @ ('"' | '\'') => {
+                        self.consume_char();
+                        self.skip_quoted(quote);
+                        continue;
+                    }
+                    _ => {}
+                }
+                self.consume_char();
+            }
+        }
+
+        fn parse_value(&mut self) -> Result<Value, ParseError> {
+            if self.eof() {
+                return Err(self.error("expected a value".to_string()));
+            }
+            match self.next_char() {
+                '0'..='9' | '.' => self.parse_length(),
+                '#' => self.parse_color(),
+                _ => {
+                    let keyword = self.parse_identifier();
+                    if keyword.is_empty() {
+                        return Err(self.error("expected a value".to_string()));
+                    }
+                    Ok(Value::Keyword(keyword))
+                }
+            }
+        }
+
+        /// A number and its unit, which only zero may leave out.
+        fn parse_length(&mut self) -> Result<Value, ParseError> {
+            let length = self.parse_float()?;
+            let unitless =
+                self.eof() || !(self.starts_with("%") || valid_identifier_char(self.next_char()));
+            if unitless && length == 0.0 {
+                return Ok(Value::Length(0.0, Unit::Px));
+            }
+            Ok(Value::Length(length, self.parse_unit()?))
+        }
+
+        fn parse_float(&mut self) -> Result<f32, ParseError> {
+            let start = self.pos;
+            let s = self.consume_while(|c| matches!(c, '0'..='9' | '.'));
+            s.parse().map_err(|_| ParseError {
+                pos: start,
+                message: format!("invalid number '{}'", s),
+            })
+        }
+
+        fn parse_unit(&mut self) -> Result<Unit, ParseError> {
+            let start = self.pos;
+            if self.starts_with("%") {
+                self.consume_char();
+                return Ok(Unit::Percent);
+            }
+            match &*self.parse_identifier().to_ascii_lowercase() {
+                "px" => Ok(Unit::Px),
+                "em" => Ok(Unit::Em),
+                "rem" => Ok(Unit::Rem),
+                "pt" => Ok(Unit::Pt),
+                unit => Err(ParseError {
+                    pos: start,
+                    message: format!("unrecognized unit '{}'", unit),
+                }),
+            }
+        }
+
+        /// `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`.
+        fn parse_color(&mut self) -> Result<Value, ParseError> {
+            self.expect_char('#')?;
+            let start = self.pos;
+            let digits = self.consume_while(|c| c.is_ascii_hexdigit());
+            let channel = |i: usize, width: usize| {
+                let value = u8::from_str_radix(&digits[i * width..(i + 1) * width], 16)
+                    .expect("hex digits");
+                if width == 1 {
+                    value * 17
+                } else {
+                    value
+                }
+            };
+            let width = match digits.len() {
+                3 | 4 => 1,
+                6 | 8 => 2,
+                _ => {
+                    return Err(ParseError {
+                        pos: start,
+                        message: format!("expected 3, 4, 6 or 8 hex digits, not '{}'", digits),
+                    })
+                }
+            };
+            Ok(Value::ColorValue(Color {
+                r: channel(0, width),
+                g: channel(1, width),
+                b: channel(2, width),
+                a: if matches!(digits.len(), 3 | 6) {
+                    255
+                } else {
+                    channel(3, width)
+                },
+            }))
+        }
+    }
+
+    /// Decodes character references such as `&amp;` and `&#x27;` in text and
+    /// attribute values, looking names up in `custom` before the built-in
+    /// table. Unknown or malformed references are kept verbatim.
+    fn decode_entities(input: &str, custom: &HashMap<String, String>) -> String {
+        let mut result = String::with_capacity(input.len());
+        let mut rest = input;
+        while let Some(amp) = rest.find('&') {
+            result.push_str(&rest[..amp]);
+            rest = &rest[amp..];
+            let Some(name) = reference_name(&rest[1..]) else {
+                result.push('&');
+                rest = &rest[1..];
+                continue;
+            };
+            let semi = name.len() + 1;
+            if let Some(value) = custom.get(name) {
+                result.push_str(value);
+            } else if let Some(c) = decode_entity(name) {
+                result.push(c);
+            } else {
+                result.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+            rest = &rest[semi + 1..];
+        }
+        result.push_str(rest);
+        result
+    }
+
+    /// The inverse of `decode_entities` for the characters that matter when
+    /// serializing; `"` is only escaped inside attribute values. Runs of
+    /// ordinary text are written in one go.
+    fn write_escaped<W: Write>(w: &mut W, text: &str, in_attribute: bool) -> io::Result<()> {
+        let mut rest = text;
+        while let Some(special) =
+            rest.find(|c| matches!(c, '&' | '<' | '>') || (in_attribute && c == '"'))
+        {
+            w.write_all(&rest.as_bytes()[..special])?;
+            let entity = match rest.as_bytes()[special] {
+                b'&' => "&amp;",
+                b'<' => "&lt;",
+                b'>' => "&gt;",
+                _ => "&quot;",
+            };
+            w.write_all(entity.as_bytes())?;
+            rest = &rest[special + 1..];
+        }
+        w.write_all(rest.as_bytes())
+    }
+
+    /// Writes `<tag name="value">`, with attributes in name order.
+    fn write_start_tag<W: Write>(w: &mut W, elem: &ElementData) -> io::Result<()> {
+        write!(w, "<{}", elem.tag_name)?;
+        let mut names: Vec<&Rc<str>> = elem.attributes.keys().collect();
+        names.sort();
+        for name in names {
+            write!(w, " {}=\"", name)?;
+            write_escaped(w, &elem.attributes[name], true)?;
+            w.write_all(b"\"")?;
+        }
+        w.write_all(b">")
+    }
+
+    fn is_void_element(elem: &ElementData) -> bool {
+        VOID_ELEMENTS
+            .iter()
+            .any(|void| void.eq_ignore_ascii_case(&elem.tag_name))
+    }
+
+    fn decode_entity(name: &str) -> Option<char> {
+        if let Ok(index) = NAMED_ENTITIES.binary_search_by(|(entity, _)| entity.cmp(&name)) {
+            return Some(NAMED_ENTITIES[index].1);
+        }
+        // As in HTML, NUL, surrogates and numbers past Unicode are U+FFFD.
+        let code = numeric_reference(name)?;
+        Some(
+            char::from_u32(code)
+                .filter(|&c| c != '\0')
+                .unwrap_or(char::REPLACEMENT_CHARACTER),
+        )
+    }
+
+    /// The name of the reference after an `&`, up to but not including its
+    /// `;`, or `None` without one. Only the characters a name can hold are
+    /// looked through, so an `&` far from any `;` costs nothing extra.
+    fn reference_name(after_amp: &str) -> Option<&str> {
+        let end = after_amp
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '#' | '-' | '_' | '.' | ':')))?;
+        after_amp[end..].starts_with(';').then(|| &after_amp[..end])
+    }
+
+    /// The code point of a numeric reference such as `#233` or `#xE9`, or
+    /// `u32::MAX` for one too large to hold.
+    fn numeric_reference(name: &str) -> Option<u32> {
+        let number = name.strip_prefix('#')?;
+        let (digits, radix) = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => (hex, 16),
+            None => (number, 10),
+        };
+        if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+            return None;
+        }
+        Some(u32::from_str_radix(digits, radix).unwrap_or(u32::MAX))
+    }
+
+    /// Named character references and what they decode to, sorted by name
+    /// for binary search. The XML five plus the common ones from HTML's much
+    /// longer list: Latin-1, typographic punctuation, arrows, maths and Greek.
+    const NAMED_ENTITIES: &[(&str, char)] = &[
+        ("AElig", '\u{c6}'),
+        ("Aacute", '\u{c1}'),
+        ("Acirc", '\u{c2}'),
+        ("Agrave", '\u{c0}'),
+        ("Aring", '\u{c5}'),
+        ("Atilde", '\u{c3}'),
+        ("Auml", '\u{c4}'),
+        ("Ccedil", '\u{c7}'),
+        ("Dagger", '\u{2021}'),
+        ("Delta", '\u{394}'),
+        ("Eacute", '\u{c9}'),
+        ("Ecirc", '\u{ca}'),
+        ("Egrave", '\u{c8}'),
+        ("Euml", '\u{cb}'),
+        ("Iacute", '\u{cd}'),
+        ("Icirc", '\u{ce}'),
+        ("Igrave", '\u{cc}'),
+        ("Iuml", '\u{cf}'),
+        ("Ntilde", '\u{d1}'),
+        ("Oacute", '\u{d3}'),
+        ("Ocirc", '\u{d4}'),
+        ("Ograve", '\u{d2}'),
+        ("Omega", '\u{3a9}'),
+        ("Oslash", '\u{d8}'),
+        ("Otilde", '\u{d5}'),
+        ("Ouml", '\u{d6}'),
+        ("Prime", '\u{2033}'),
+        ("Sigma", '\u{3a3}'),
+        ("Uacute", '\u{da}'),
+        ("Ucirc", '\u{db}'),
+        ("Ugrave", '\u{d9}'),
+        ("Uuml", '\u{dc}'),
+        ("Yacute", '\u{dd}'),
+        ("aacute", '\u{e1}'),
+        ("acirc", '\u{e2}'),
+        ("acute", '\u{b4}'),
+        ("aelig", '\u{e6}'),
+        ("agrave", '\u{e0}'),
+        ("alpha", '\u{3b1}'),
+        ("amp", '&'),
+        ("and", '\u{2227}'),
+        ("apos", '\''),
+        ("aring", '\u{e5}'),
+        ("asymp", '\u{2248}'),
+        ("atilde", '\u{e3}'),
+        ("auml", '\u{e4}'),
+        ("bdquo", '\u{201e}'),
+        ("beta", '\u{3b2}'),
+        ("brvbar", '\u{a6}'),
+        ("bull", '\u{2022}'),
+        ("cap", '\u{2229}'),
+        ("ccedil", '\u{e7}'),
+        ("cedil", '\u{b8}'),
+        ("cent", '\u{a2}'),
+        ("clubs", '\u{2663}'),
+        ("copy", '\u{a9}'),
+        ("cup", '\u{222a}'),
+        ("curren", '\u{a4}'),
+        ("dagger", '\u{2020}'),
+        ("darr", '\u{2193}'),
+        ("deg", '\u{b0}'),
+        ("delta", '\u{3b4}'),
+        ("diams", '\u{2666}'),
+        ("divide", '\u{f7}'),
+        ("eacute", '\u{e9}'),
+        ("ecirc", '\u{ea}'),
+        ("egrave", '\u{e8}'),
+        ("empty", '\u{2205}'),
+        ("emsp", '\u{2003}'),
+        ("ensp", '\u{2002}'),
+        ("epsilon", '\u{3b5}'),
+        ("equiv", '\u{2261}'),
+        ("euml", '\u{eb}'),
+        ("euro", '\u{20ac}'),
+        ("exist", '\u{2203}'),
+        ("forall", '\u{2200}'),
+        ("frac12", '\u{bd}'),
+        ("frac14", '\u{bc}'),
+        ("frac34", '\u{be}'),
+        ("gamma", '\u{3b3}'),
+        ("ge", '\u{2265}'),
+        ("gt", '>'),
+        ("hArr", '\u{21d4}'),
+        ("harr", '\u{2194}'),
+        ("hearts", '\u{2665}'),
+        ("hellip", '\u{2026}'),
+        ("iacute", '\u{ed}'),
+        ("icirc", '\u{ee}'),
+        ("iexcl", '\u{a1}'),
+        ("igrave", '\u{ec}'),
+        ("infin", '\u{221e}'),
+        ("int", '\u{222b}'),
+        ("iquest", '\u{bf}'),
+        ("isin", '\u{2208}'),
+        ("iuml", '\u{ef}'),
+        ("lArr", '\u{21d0}'),
+        ("lambda", '\u{3bb}'),
+        ("laquo", '\u{ab}'),
+        ("larr", '\u{2190}'),
+        ("ldquo", '\u{201c}'),
+        ("le", '\u{2264}'),
+        ("loz", '\u{25ca}'),
+        ("lrm", '\u{200e}'),
+        ("lsaquo", '\u{2039}'),
+        ("lsquo", '\u{2018}'),
+        ("lt", '<'),
+        ("macr", '\u{af}'),
+        ("mdash", '\u{2014}'),
+        ("micro", '\u{b5}'),
+        ("middot", '\u{b7}'),
+        ("minus", '\u{2212}'),
+        ("mu", '\u{3bc}'),
+        ("nabla", '\u{2207}'),
+        ("nbsp", '\u{a0}'),
+        ("ndash", '\u{2013}'),
+        ("ne", '\u{2260}'),
+        ("not", '\u{ac}'),
+        ("ntilde", '\u{f1}'),
+        ("oacute", '\u{f3}'),
+        ("ocirc", '\u{f4}'),
+        ("ograve", '\u{f2}'),
+        ("oline", '\u{203e}'),
+        ("omega", '\u{3c9}'),
+        ("or", '\u{2228}'),
+        ("ordf", '\u{aa}'),
+        ("ordm", '\u{ba}'),
+        ("oslash", '\u{f8}'),
+        ("otilde", '\u{f5}'),
+        ("ouml", '\u{f6}'),
+        ("para", '\u{b6}'),
+        ("part", '\u{2202}'),
+        ("permil", '\u{2030}'),
+        ("phi", '\u{3c6}'),
+        ("pi", '\u{3c0}'),
+        ("plusmn", '\u{b1}'),
+        ("pound", '\u{a3}'),
+        ("prime", '\u{2032}'),
+        ("prod", '\u{220f}'),
+        ("quot", '"'),
+        ("rArr", '\u{21d2}'),
+        ("radic", '\u{221a}'),
+        ("raquo", '\u{bb}'),
+        ("rarr", '\u{2192}'),
+        ("rdquo", '\u{201d}'),
+        ("reg", '\u{ae}'),
+        ("rlm", '\u{200f}'),
+        ("rsaquo", '\u{203a}'),
+        ("rsquo", '\u{2019}'),
+        ("sbquo", '\u{201a}'),
+        ("sect", '\u{a7}'),
+        ("shy", '\u{ad}'),
+        ("sigma", '\u{3c3}'),
+        ("spades", '\u{2660}'),
+        ("sum", '\u{2211}'),
+        ("sup1", '\u{b9}'),
+        ("sup2", '\u{b2}'),
+        ("sup3", '\u{b3}'),
+        ("szlig", '\u{df}'),
+        ("tau", '\u{3c4}'),
+        ("there4", '\u{2234}'),
+        ("theta", '\u{3b8}'),
+        ("thinsp", '\u{2009}'),
+        ("times", '\u{d7}'),
+        ("trade", '\u{2122}'),
+        ("uacute", '\u{fa}'),
+        ("uarr", '\u{2191}'),
+        ("ucirc", '\u{fb}'),
+        ("ugrave", '\u{f9}'),
+        ("uml", '\u{a8}'),
+        ("uuml", '\u{fc}'),
+        ("yacute", '\u{fd}'),
+        ("yen", '\u{a5}'),
+        ("yuml", '\u{ff}'),
+        ("zwj", '\u{200d}'),
+        ("zwnj", '\u{200c}'),
+    ];
+
+    /// Elements that never have content, and so never a closing tag.
+    const VOID_ELEMENTS: &[&str] = &[
+        "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source",
+        "track", "wbr",
+    ];
+
+    /// Elements that start foreign (SVG or MathML) content.
+    const FOREIGN_ROOTS: &[&str] = &["math", "svg"];
+
+    /// Elements that `recover` reopens after misnested tags, like HTML's
+    /// "active formatting elements".
+    const FORMATTING_ELEMENTS: &[&str] = &[
+        "a", "b", "big", "code", "em", "font", "i", "nobr", "s", "small", "strike", "strong", "tt",
+        "u",
+    ];
+
+    /// Elements whose end tag HTML lets authors leave out, with the start
+    /// tags that close them: `<li>a<li>b` is two list items. They're also
+    /// closed by the end tag of an ancestor and by the end of input.
+    const OPTIONAL_END_TAGS: &[(&str, &[&str])] = &[
+        ("li", &["li"]),
+        ("dt", &["dd", "dt"]),
+        ("dd", &["dd", "dt"]),
+        (
+            "p",
+            &[
+                "address",
+                "article",
+                "aside",
+                "blockquote",
+                "dd",
+                "details",
+                "dialog",
+                "div",
+                "dl",
+                "dt",
+                "fieldset",
+                "figcaption",
+                "figure",
+                "footer",
+                "form",
+                "h1",
+                "h2",
+                "h3",
+                "h4",
+                "h5",
+                "h6",
+                "header",
+                "hgroup",
+                "hr",
+                "li",
+                "main",
+                "menu",
+                "nav",
+                "ol",
+                "p",
+                "pre",
+                "section",
+                "table",
+                "ul",
+            ],
+        ),
+        ("option", &["optgroup", "option"]),
+        ("optgroup", &["optgroup"]),
+        ("rt", &["rp", "rt"]),
+        ("rp", &["rp", "rt"]),
+        ("thead", &["tbody", "tfoot"]),
+        ("tbody", &["tbody", "tfoot"]),
+        ("tfoot", &["tbody"]),
+        ("tr", &["tbody", "tfoot", "thead", "tr"]),
+        ("td", &["tbody", "td", "tfoot", "th", "thead", "tr"]),
+        ("th", &["tbody", "td", "tfoot", "th", "thead", "tr"]),
+    ];
+
+    /// The start tags that close `tag_name`, if its end tag is optional.
+    fn optional_end_tag_closers(tag_name: &str) -> Option<&'static [&'static str]> {
+        OPTIONAL_END_TAGS
+            .iter()
+            .find(|(tag, _)| tag.eq_ignore_ascii_case(tag_name))
+            .map(|&(_, closers)| closers)
+    }
+
+    /// Elements whose content is literal text up to their closing tag.
+    const RAW_TEXT_ELEMENTS: &[&str] = &[
+        "iframe", "noembed", "noframes", "noscript", "script", "style", "xmp",
+    ];
+
+    /// Like raw text elements, but character references are decoded.
+    const RCDATA_ELEMENTS: &[&str] = &["textarea", "title"];
+
+    const HTML_TAGS: &[&str] = &[
+        "a",
+        "abbr",
+        "address",
+        "area",
+        "article",
+        "aside",
+        "audio",
+        "b",
+        "base",
+        "bdi",
+        "bdo",
+        "blockquote",
+        "body",
+        "br",
+        "button",
+        "canvas",
+        "caption",
+        "center",
+        "cite",
+        "code",
+        "col",
+        "colgroup",
+        "data",
+        "datalist",
+        "dd",
+        "del",
+        "details",
+        "dfn",
+        "dialog",
+        "div",
+        "dl",
+        "dt",
+        "em",
+        "embed",
+        "fieldset",
+        "figcaption",
+        "figure",
+        "font",
+        "footer",
+        "form",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "head",
+        "header",
+        "hgroup",
+        "hr",
+        "html",
+        "i",
+        "iframe",
+        "img",
+        "input",
+        "ins",
+        "kbd",
+        "label",
+        "legend",
+        "li",
+        "link",
+        "main",
+        "map",
+        "mark",
+        "menu",
+        "meta",
+        "meter",
+        "nav",
+        "noscript",
+        "object",
+        "ol",
+        "optgroup",
+        "option",
+        "output",
+        "p",
+        "param",
+        "picture",
+        "pre",
+        "progress",
+        "q",
+        "rp",
+        "rt",
+        "ruby",
+        "s",
+        "samp",
+        "script",
+        "search",
+        "section",
+        "select",
+        "slot",
+        "small",
+        "source",
+        "span",
+        "strong",
+        "style",
+        "sub",
+        "summary",
+        "sup",
+        "table",
+        "tbody",
+        "td",
+        "template",
+        "textarea",
+        "tfoot",
+        "th",
+        "thead",
+        "time",
+        "title",
+        "tr",
+        "track",
+        "u",
+        "ul",
+        "var",
+        "video",
+        "wbr",
+    ];
+
+    fn is_html_tag(name: &str) -> bool {
+        HTML_TAGS.iter().any(|tag| tag.eq_ignore_ascii_case(name))
+    }
+
+    fn valid_identifier_char(c: char) -> bool {
+        matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_')
+    }
+
+    pub struct Node {
+        children: Vec<Node>,
+        node_type: NodeType,
+        /// Where the node came from in the parsed input. `None` for nodes
+        /// the parser synthesized or that were built by hand.
+        span: Option<Span>,
+    }
+
+    /// An immutable node whose children are reference counted, from
+    /// `Node::into_shared`. Cloning an `Rc<SharedNode>` is cheap and shares
+    /// the whole subtree, so one parsed template can be handed to many
+    /// renderers. The price is that it can't be edited in place; edit the
+    /// `Node` before converting.
+    pub struct SharedNode {
+        children: Vec<Rc<SharedNode>>,
+        node_type: NodeType,
+        span: Option<Span>,
+    }
+
+    impl SharedNode {
+        pub fn children(&self) -> &[Rc<SharedNode>] {
+            &self.children
+        }
+
+        pub fn node_type(&self) -> &NodeType {
+            &self.node_type
+        }
+
+        pub fn element(&self) -> Option<&ElementData> {
+            match self.node_type {
+                NodeType::Element(ref elem) => Some(elem),
+                NodeType::Text(_) | NodeType::Comment(_) => None,
+            }
+        }
+
+        pub fn span(&self) -> Option<Span> {
+            self.span
+        }
+
+        /// All descendant text concatenated in document order.
+        pub fn text_content(&self) -> String {
+            match self.node_type {
+                NodeType::Text(ref text) => text.clone(),
+                NodeType::Element(_) => self.children.iter().map(|c| c.text_content()).collect(),
+                NodeType::Comment(_) => String::new(),
+            }
+        }
+    }
+
+    /// A byte range `start..end` of the parser's input.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Span {
+        pub start: usize,
+        pub end: usize,
+    }
+
+    /// A change to parsed input, for `ParserBuilder::reparse`: the bytes
+    /// `start..old_end` of the old input became `start..new_end` of the new.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Edit {
+        pub start: usize,
+        pub old_end: usize,
+        pub new_end: usize,
+    }
+
+    impl Edit {
+        /// Where `pos`, at or after `old_end` in the old input, is now.
+        fn shift(&self, pos: usize) -> usize {
+            pos - self.old_end + self.new_end
+        }
+    }
+
+    pub enum NodeType {
+        Text(String),
+        Element(ElementData),
+        Comment(String),
+    }
+
+    pub struct ElementData {
+        tag_name: Rc<str>,
+        attributes: AttrMap,
+        /// See `raw_attributes`.
+        raw_attributes: Option<Box<[(String, String)]>>,
+    }
+
+    type AttrMap = HashMap<Rc<str>, String>;
+
+    fn text(data: String) -> Node {
+        Node {
+            children: Vec::new(),
+            node_type: NodeType::Text(data),
+            span: None,
+        }
+    }
+
+    fn comment(data: String) -> Node {
+        Node {
+            children: Vec::new(),
+            node_type: NodeType::Comment(data),
+            span: None,
+        }
+    }
+
+    fn elem(name: impl Into<Rc<str>>, attrs: AttrMap, children: Vec<Node>) -> Node {
+        Node {
+            children,
+            node_type: NodeType::Element(ElementData {
+                tag_name: name.into(),
+                attributes: attrs,
+                raw_attributes: None,
+            }),
+            span: None,
+        }
+    }
+
+    impl Node {
+        /// The first descendant element matching `selector`, in document
+        /// order.
+        pub fn query_selector(&self, selector: &str) -> Result<Option<&Node>, ParseError> {
+            Ok(self.query_selector_all(selector)?.into_iter().next())
+        }
+
+        /// Every descendant element matching `selector`, in document order.
+        /// This node is treated as the root when matching structural
+        /// pseudo-classes, and a selector ending in `:root` matches it,
+        /// first, rather than a descendant. Like the DOM, the contents of `<template>`
+        /// elements are not searched; see `query_selector_all_with`.
+        pub fn query_selector_all(&self, selector: &str) -> Result<Vec<&Node>, ParseError> {
+            self.query_selector_all_with(selector, false)
+        }
+
+        /// `query_selector` that can also search inside `<template>` elements.
+        pub fn query_selector_with(
+            &self,
+            selector: &str,
+            include_templates: bool,
+        ) -> Result<Option<&Node>, ParseError> {
+            let found = self.query_selector_all_with(selector, include_templates)?;
+            Ok(found.into_iter().next())
+        }
+
+        /// `query_selector_all` that can also search inside `<template>`
+        /// elements.
+        pub fn query_selector_all_with(
+            &self,
+            selector: &str,
+            include_templates: bool,
+        ) -> Result<Vec<&Node>, ParseError> {
+            let selectors = parse_selector_list(selector)?;
+            let mut found = Vec::new();
+            let mut path = vec![(std::slice::from_ref(self), 0)];
+            // This node is no descendant of itself, but `:root` names it.
+            if let Some(elem) = self.element() {
+                let context = TreeContext { path: &path };
+                let is_root = |selector: &Selector| {
+                    let pseudo_classes = &selector.subject().pseudo_classes;
+                    pseudo_classes
+                        .iter()
+                        .any(|p| matches!(p, PseudoClass::Root))
+                };
+                if selectors
+                    .iter()
+                    .any(|s| is_root(s) && matches(elem, Some(context), s))
+                {
+                    found.push(self);
+                }
+            }
+            walk_descendants(&mut path, include_templates, &mut |context| {
+                let node = context.node();
+                if let Some(elem) = node.element() {
+                    if selectors.iter().any(|s| matches(elem, Some(context), s)) {
+                        found.push(node);
+                    }
+                }
+            });
+            Ok(found)
+        }
+
+        /// An element with no span, for building trees by hand. A repeated
+        /// attribute name keeps its last value.
+        pub fn new_element(
+            tag_name: &str,
+            attributes: &[(&str, &str)],
+            children: Vec<Node>,
+        ) -> Node {
+            let attributes = attributes
+                .iter()
+                .map(|&(name, value)| (Rc::from(name), value.to_string()))
+                .collect();
+            elem(tag_name, attributes, children)
+        }
+
+        /// A text node; `data` is the text itself, not escaped markup.
+        pub fn new_text(data: &str) -> Node {
+            text(data.to_string())
+        }
+
+        pub fn new_comment(data: &str) -> Node {
+            comment(data.to_string())
+        }
+
+        /// Adds `child` after this node's last child.
+        pub fn append_child(&mut self, child: Node) {
+            self.children.push(child);
+        }
+
+        /// Inserts `child` so that it becomes child number `index`, shifting
+        /// later children along. `index` may equal the number of children,
+        /// which appends; anything larger gives `child` back.
+        // Handing the node back unboxed is the point; it was never boxed.
+        #[allow(clippy::result_large_err)]
+        pub fn insert_child_at(&mut self, index: usize, child: Node) -> Result<(), Node> {
+            if index > self.children.len() {
+                return Err(child);
+            }
+            self.children.insert(index, child);
+            Ok(())
+        }
+
+        /// Removes and returns child number `index`, or `None` if there is no
+        /// such child.
+        pub fn remove_child(&mut self, index: usize) -> Option<Node> {
+            (index < self.children.len()).then(|| self.children.remove(index))
+        }
+
+        /// Deletes every descendant element matching `selector`, along with
+        /// its children. Matching happens against the tree before anything is
+        /// removed, so positional selectors behave predictably. Template
+        /// contents are included, so sanitizing can't miss them.
+        pub fn remove_matching(&mut self, selector: &str) -> Result<(), ParseError> {
+            let selectors = parse_selector_list(selector)?;
+            let mut doomed: Vec<Vec<usize>> = Vec::new();
+            let mut path = vec![(std::slice::from_ref(&*self), 0)];
+            walk_descendants(&mut path, true, &mut |context| {
+                if let Some(elem) = context.node().element() {
+                    if selectors.iter().any(|s| matches(elem, Some(context), s)) {
+                        doomed.push(context.path[1..].iter().map(|&(_, i)| i).collect());
+                    }
+                }
+            });
+            // Later paths never pass through earlier siblings, so removing in
+            // reverse document order keeps the remaining paths valid.
+            for path in doomed.iter().rev() {
+                let (last, parents) = path.split_last().expect("descendants have a path");
+                let mut parent = &mut *self;
+                for &index in parents {
+                    parent = &mut parent.children[index];
+                }
+                parent.children.remove(*last);
+            }
+            Ok(())
+        }
+
+        /// Converts the tree into a `SharedNode` tree without copying any
+        /// text or attributes.
+        pub fn into_shared(self) -> Rc<SharedNode> {
+            Rc::new(SharedNode {
+                children: self.children.into_iter().map(Node::into_shared).collect(),
+                node_type: self.node_type,
+                span: self.span,
+            })
+        }
+
+        /// Renames every element in the subtree, this node included, whose
+        /// tag is `from` (ignoring case) to `to`, keeping its attributes and
+        /// children.
+        pub fn rename_tag(&mut self, from: &str, to: &str) {
+            if let NodeType::Element(ref mut elem) = self.node_type {
+                if elem.tag_name.eq_ignore_ascii_case(from) {
+                    elem.tag_name = to.into();
+                }
+            }
+            for child in &mut self.children {
+                child.rename_tag(from, to);
+            }
+        }
+
+        /// Merges adjacent text nodes and drops empty ones throughout the
+        /// subtree, like the DOM's `Node.normalize()`.
+        pub fn normalize(&mut self) {
+            let mut children: Vec<Node> = Vec::with_capacity(self.children.len());
+            for mut child in std::mem::take(&mut self.children) {
+                if let NodeType::Text(ref data) = child.node_type {
+                    if data.is_empty() {
+                        continue;
+                    }
+                    if let Some(NodeType::Text(previous)) =
+                        children.last_mut().map(|last| &mut last.node_type)
+                    {
+                        previous.push_str(data);
+                        continue;
+                    }
+                }
+                child.normalize();
+                children.push(child);
+            }
+            self.children = children;
+        }
+
+        /// Serializes the node back to HTML. Attributes are written in name
+        /// order so the output is deterministic.
+        pub fn to_html(&self) -> String {
+            let mut html = Vec::new();
+            self.write_html(&mut html)
+                .expect("writing to a Vec cannot fail");
+            String::from_utf8(html).expect("serialized HTML is valid UTF-8")
+        }
+
+        /// The node itself, including its own tags. Same as `to_html`.
+        pub fn outer_html(&self) -> String {
+            self.to_html()
+        }
+
+        /// Just the node's children serialized; for a text node, its escaped
+        /// content.
+        pub fn inner_html(&self) -> String {
+            match self.node_type {
+                NodeType::Element(_) => self.children.iter().map(Node::to_html).collect(),
+                NodeType::Text(_) | NodeType::Comment(_) => self.to_html(),
+            }
+        }
+
+        /// Streams the same output as `to_html` into `w` without building
+        /// the whole document in memory.
+        pub fn write_html<W: Write>(&self, w: &mut W) -> io::Result<()> {
+            self.write_html_in(w, false)
+        }
+
+        /// Writes the node so that parsing the output gives the same tree:
+        /// inside `<svg>` or `<math>` nothing is void, so `<br>` keeps its
+        /// closing tag, and the text of `<script>` and `<style>` is written
+        /// unescaped since it's parsed literally.
+        fn write_html_in<W: Write>(&self, w: &mut W, in_foreign_content: bool) -> io::Result<()> {
+            match self.node_type {
+                NodeType::Text(ref text) => write_escaped(w, text, false),
+                NodeType::Comment(ref data) => write!(w, "<!--{}-->", data),
+                NodeType::Element(ref elem) => {
+                    write_start_tag(w, elem)?;
+                    let is_tag = |tags: &[&str]| {
+                        tags.iter()
+                            .any(|tag| tag.eq_ignore_ascii_case(&elem.tag_name))
+                    };
+                    let foreign = in_foreign_content || is_tag(FOREIGN_ROOTS);
+                    if !foreign && is_void_element(elem) && self.children.is_empty() {
+                        return Ok(());
+                    }
+                    for child in &self.children {
+                        match child.node_type {
+                            NodeType::Text(ref text) if is_tag(RAW_TEXT_ELEMENTS) => {
+                                w.write_all(text.as_bytes())?
+                            }
+                            _ => child.write_html_in(w, foreign)?,
+                        }
+                    }
+                    write!(w, "</{}>", elem.tag_name)
+                }
+            }
+        }
+
+        pub fn children(&self) -> &[Node] {
+            &self.children
+        }
+
+        pub fn node_type(&self) -> &NodeType {
+            &self.node_type
+        }
+
+        /// The element's tag and attributes, or `None` for text and
+        /// comments.
+        pub fn element(&self) -> Option<&ElementData> {
+            match self.node_type {
+                NodeType::Element(ref elem) => Some(elem),
+                NodeType::Text(_) | NodeType::Comment(_) => None,
+            }
+        }
+
+        /// The byte range of the input this node was parsed from, from the
+        /// start of its opening tag to the end of its closing tag.
+        pub fn span(&self) -> Option<Span> {
+            self.span
+        }
+
+        /// The exact markup this node was parsed from, formatting and all.
+        /// `original_input` must be the string that was parsed.
+        pub fn source_text<'a>(&self, original_input: &'a str) -> Option<&'a str> {
+            let span = self.span?;
+            original_input.get(span.start..span.end)
+        }
+
+        /// Every node in this subtree, this node included, paired with its
+        /// depth below this node (which is at depth 0). Nodes come depth
+        /// first in document order: a node, then all of its descendants, then
+        /// its next sibling.
+        pub fn walk(&self) -> impl Iterator<Item = (usize, &Node)> {
+            let mut stack = vec![(0, self)];
+            std::iter::from_fn(move || {
+                let (depth, node) = stack.pop()?;
+                stack.extend(node.children.iter().rev().map(|child| (depth + 1, child)));
+                Some((depth, node))
+            })
+        }
+
+        /// Every element in this subtree in document order, this node included.
+        pub fn elements(&self) -> impl Iterator<Item = &ElementData> {
+            let mut stack = vec![self];
+            std::iter::from_fn(move || {
+                while let Some(node) = stack.pop() {
+                    stack.extend(node.children.iter().rev());
+                    if let Some(elem) = node.element() {
+                        return Some(elem);
+                    }
+                }
+                None
+            })
+        }
+
+        /// Every element node in this subtree, this node included, for which
+        /// `predicate` holds, in document order.
+        pub fn find(&self, predicate: impl Fn(&ElementData) -> bool) -> Vec<&Node> {
+            let mut found = Vec::new();
+            let mut stack = vec![self];
+            while let Some(node) = stack.pop() {
+                stack.extend(node.children.iter().rev());
+                if node.element().is_some_and(&predicate) {
+                    found.push(node);
+                }
+            }
+            found
+        }
+
+        /// All descendant text concatenated in document order.
+        pub fn text_content(&self) -> String {
+            match self.node_type {
+                NodeType::Text(ref text) => text.clone(),
+                NodeType::Element(_) => self.children.iter().map(Node::text_content).collect(),
+                NodeType::Comment(_) => String::new(),
+            }
+        }
+
+        /// The text roughly as a browser would render it, like the DOM's
+        /// `innerText`. Whitespace collapses as in rendering, except inside
+        /// `<pre>`. Each of `INNER_TEXT_BLOCKS` starts and ends a line, with
+        /// a blank line around paragraphs; `<br>` breaks the line and table
+        /// cells are separated by tabs. Two inline elements with nothing
+        /// between them are separated by a space, so `<b>a</b><i>b</i>` reads
+        /// `a b`. Scripts, styles, templates, the head and elements marked
+        /// `hidden` are left out.
+        pub fn inner_text(&self) -> String {
+            let mut text = InnerText::default();
+            text.node(self, false);
+            text.out
+        }
+
+        /// Whether this is a `<template>`, whose children are parsed as usual
+        /// but are inert: not rendered and not searched by default.
+        pub fn is_template(&self) -> bool {
+            self.element()
+                .is_some_and(|elem| elem.tag_name.eq_ignore_ascii_case("template"))
+        }
+
+        /// The first element in document order, this node included, whose tag
+        /// is `tag_name`.
+        fn first_by_tag(&self, tag_name: &str) -> Option<&Node> {
+            if self
+                .element()
+                .is_some_and(|elem| elem.tag_name.eq_ignore_ascii_case(tag_name))
+            {
+                return Some(self);
+            }
+            self.children
+                .iter()
+                .find_map(|child| child.first_by_tag(tag_name))
+        }
+        /// Follows a sequence of child indices down from this node.
+        pub fn get_by_path(&self, path: &[usize]) -> Option<&Node> {
+            path.iter()
+                .try_fold(self, |node, &index| node.children.get(index))
+        }
+
+        /// The child indices leading from this node to `target`, which must be
+        /// the very same node (compared by address), not merely an equal one.
+        pub fn path_of(&self, target: &Node) -> Option<Vec<usize>> {
+            if std::ptr::eq(self, target) {
+                return Some(Vec::new());
+            }
+            self.children.iter().enumerate().find_map(|(index, child)| {
+                child.path_of(target).map(|mut path| {
+                    path.insert(0, index);
+                    path
+                })
+            })
+        }
+    }
+
+    /// Elements `inner_text` puts on lines of their own.
+    const INNER_TEXT_BLOCKS: &[&str] = &[
+        "address",
+        "article",
+        "aside",
+        "blockquote",
+        "body",
+        "caption",
+        "dd",
+        "details",
+        "dialog",
+        "div",
+        "dl",
+        "dt",
+        "fieldset",
+        "figcaption",
+        "figure",
+        "footer",
+        "form",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "header",
+        "hr",
+        "html",
+        "legend",
+        "li",
+        "main",
+        "nav",
+        "ol",
+        "p",
+        "pre",
+        "section",
+        "summary",
+        "table",
+        "tbody",
+        "tfoot",
+        "thead",
+        "tr",
+        "ul",
+    ];
+
+    /// Elements that aren't rendered, so `inner_text` skips their content.
+    const INNER_TEXT_SKIPPED: &[&str] = &["head", "script", "style", "template"];
+
+    /// The output of `Node::inner_text` as it's built. Line breaks and spaces
+    /// are held back until more text arrives, so none are left dangling at
+    /// the start or end, or doubled up between blocks.
+    #[derive(Default)]
+    struct InnerText {
+        out: String,
+        breaks: usize,
+        space: bool,
+    }
+
+    impl InnerText {
+        fn node(&mut self, node: &Node, in_pre: bool) {
+            let elem = match node.node_type {
+                NodeType::Text(ref text) => return self.text(text, in_pre),
+                NodeType::Comment(_) => return,
+                NodeType::Element(ref elem) => elem,
+            };
+            let tag = elem.tag_name.to_ascii_lowercase();
+            if INNER_TEXT_SKIPPED.contains(&tag.as_str()) || elem.has_attribute("hidden") {
+                return;
+            }
+            if tag == "br" {
+                self.flush();
+                self.out.push('\n');
+                return;
+            }
+            let breaks = match tag.as_str() {
+                "p" => 2,
+                tag if INNER_TEXT_BLOCKS.contains(&tag) => 1,
+                _ => 0,
+            };
+            self.breaks = self.breaks.max(breaks);
+            let in_pre = in_pre || tag == "pre";
+            let is_cell = |node: &Node| {
+                node.element().is_some_and(|elem| {
+                    elem.tag_name.eq_ignore_ascii_case("td")
+                        || elem.tag_name.eq_ignore_ascii_case("th")
+                })
+            };
+            let is_inline = |node: &Node| {
+                node.element().is_some_and(|elem| {
+                    !INNER_TEXT_BLOCKS
+                        .iter()
+                        .any(|block| block.eq_ignore_ascii_case(&elem.tag_name))
+                })
+            };
+            let mut previous: Option<&Node> = None;
+            for child in &node.children {
+                if tag == "tr" && is_cell(child) && previous.is_some_and(is_cell) {
+                    self.flush();
+                    self.out.push('\t');
+                } else if previous.is_some_and(is_inline) && is_inline(child) {
+                    self.space = true;
+                }
+                self.node(child, in_pre);
+                if !matches!(child.node_type, NodeType::Comment(_)) {
+                    previous = Some(child);
+                }
+            }
+            self.breaks = self.breaks.max(breaks);
+        }
+
+        fn text(&mut self, text: &str, in_pre: bool) {
+            if in_pre {
+                if !text.is_empty() {
+                    self.flush();
+                    self.out.push_str(text);
+                }
+                return;
+            }
+            if text.starts_with(|c: char| c.is_ascii_whitespace()) {
+                self.space = true;
+            }
+            let mut words = text.split_ascii_whitespace().peekable();
+            while let Some(word) = words.next() {
+                self.flush();
+                self.out.push_str(word);
+                self.space = words.peek().is_some();
+            }
+            if text.ends_with(|c: char| c.is_ascii_whitespace()) {
+                self.space = true;
+            }
+        }
+
+        /// Writes the line breaks or space held back, before more text.
+        fn flush(&mut self) {
+            if !self.out.is_empty() {
+                if self.breaks > 0 {
+                    self.out.extend(std::iter::repeat_n('\n', self.breaks));
+                } else if self.space && !self.out.ends_with(['\n', '\t']) {
+                    self.out.push(' ');
+                }
+            }
+            self.breaks = 0;
+            self.space = false;
+        }
+    }
+
+    const HEAD_ELEMENTS: &[&str] = &[
+        "base", "link", "meta", "noscript", "script", "style", "template", "title",
+    ];
+
+    /// Rebuilds top-level nodes into `html > head + body`. Explicit `html`,
+    /// `head` and `body` elements are merged in, keeping their attributes.
+    /// Head-only elements and comments stay in the head until the first
+    /// piece of body content is seen.
+    fn imply_document_structure(nodes: Vec<Node>) -> Node {
+        let is_tag = |node: &Node, tag: &str| {
+            node.element()
+                .is_some_and(|elem| elem.tag_name.eq_ignore_ascii_case(tag))
+        };
+        let mut html_attrs = HashMap::new();
+        let mut nodes = nodes;
+        if nodes.len() == 1 && is_tag(&nodes[0], "html") {
+            let html = nodes.remove(0);
+            if let NodeType::Element(elem) = html.node_type {
+                html_attrs = elem.attributes;
+            }
+            nodes = html.children;
+        }
+
+        let mut head = elem("head".to_string(), HashMap::new(), Vec::new());
+        let mut body = elem("body".to_string(), HashMap::new(), Vec::new());
+        let mut in_body = false;
+        for node in nodes {
+            let target = if is_tag(&node, "head") {
+                &mut head
+            } else if is_tag(&node, "body") {
+                in_body = true;
+                &mut body
+            } else {
+                let belongs_in_head = matches!(node.node_type, NodeType::Comment(_))
+                    || HEAD_ELEMENTS.iter().any(|tag| is_tag(&node, tag));
+                in_body |= !belongs_in_head;
+                if in_body {
+                    body.children.push(node);
+                } else {
+                    head.children.push(node);
+                }
+                continue;
+            };
+            // Merge an explicit head or body into the one being built.
+            if let (NodeType::Element(target_elem), NodeType::Element(elem)) =
+                (&mut target.node_type, node.node_type)
+            {
+                for (name, value) in elem.attributes {
+                    target_elem.attributes.entry(name).or_insert(value);
+                }
+            }
+            target.children.extend(node.children);
+        }
+        elem("html".to_string(), html_attrs, vec![head, body])
+    }
+
+    fn source(source: String) -> Result<Node, ParseError> {
+        ParserBuilder::new().parse(source)
+    }
+
+    /// Parses `html` as the content of a `context_tag` element, as setting
+    /// `innerHTML` does. In a `<script>` or `<style>` the input is a single
+    /// text node taken literally, and in a `<title>` or `<textarea>` one with
+    /// its entities decoded. In `<svg>` or `<math>` any element may
+    /// self-close. Every other context, tables included, parses the input as
+    /// an ordinary fragment, since this parser has no per-element insertion
+    /// rules.
+    pub fn parse_fragment_in_context(
+        html: &str,
+        context_tag: &str,
+    ) -> Result<Vec<Node>, ParseError> {
+        Parser::new(html.to_string()).parse_in_context(context_tag)
+    }
+
+    /// Parses whatever it can and never fails, for scraping real-world HTML.
+    /// Parsing runs in `recover` mode, and everything it works around comes
+    /// back as an error alongside the tree, in input order. Errors `recover`
+    /// alone would stop at are stepped past as well:
+    ///
+    /// - An unquoted attribute value runs to whitespace or `>`, and one
+    ///   whose quote is never closed, like a comment or CDATA section that
+    ///   isn't, runs to the end of the input, as does an unclosed start tag.
+    /// - A character that can't start an attribute name is skipped.
+    /// - Attributes past the limit are dropped, the content of elements
+    ///   nested past the depth limit becomes their siblings, and parsing
+    ///   stops at the node limit. Input past the size limit isn't parsed.
+    pub fn parse_lossy(html: &str) -> (Node, Vec<ParseError>) {
+        let mut builder = ParserBuilder::new().recover(true);
+        builder.options.lossy = true;
+        let max_input_size = builder.options.max_input_size;
+        let mut end = html.len();
+        let too_large = (end > max_input_size).then(|| ParseError {
+            pos: max_input_size,
+            message: format!("input is larger than {} bytes", max_input_size),
+        });
+        if too_large.is_some() {
+            end = max_input_size;
+            while !html.is_char_boundary(end) {
+                end -= 1;
+            }
+        }
+        let (root, warnings) = match builder.parse_with_warnings(html[..end].to_string()) {
+            Ok(parsed) => parsed,
+            // Not expected, since lossy mode makes every error a warning.
+            Err(error) => {
+                let root = Document {
+                    doctype: None,
+                    nodes: Vec::new(),
+                }
+                .into_node();
+                let warning = Warning {
+                    pos: error.pos,
+                    message: error.message,
+                };
+                (root, vec![warning])
+            }
+        };
+        let errors = warnings
+            .into_iter()
+            .map(|warning| ParseError {
+                pos: warning.pos,
+                message: warning.message,
+            })
+            .chain(too_large)
+            .collect();
+        (root, errors)
+    }
+
+    pub fn parse_css(source: &str) -> Result<Stylesheet, ParseError> {
+        Parser::new(source.to_string()).parse_stylesheet()
+    }
+
+    /// Checks that `xml` is well-formed XML, as XHTML must be, reporting
+    /// every violation found rather than just the first. Parsing is strict:
+    /// every element needs an end tag or `/>`, as none are void or have
+    /// optional end tags; names are case-sensitive; attributes need quoted
+    /// values and may not repeat; `<script>` and `<style>` hold markup like
+    /// any other element; only XML's five named entities are defined; and
+    /// there must be exactly one root element. Violations of these that the
+    /// parser can step past are collected along the way, but a broken
+    /// element structure, such as a missing end tag, ends the check.
+    pub fn validate_xml(xml: &str) -> Result<(), Vec<ParseError>> {
+        let mut builder = ParserBuilder::new().void_elements(HashSet::new());
+        builder.options.xml = true;
+        let mut parser = builder.build(xml.to_string());
+        let parsed = parser.parse_top_level();
+        let mut errors: Vec<ParseError> = std::mem::take(&mut parser.warnings)
+            .into_iter()
+            .map(|warning| ParseError {
+                pos: warning.pos,
+                message: warning.message,
+            })
+            .collect();
+        match parsed {
+            Ok(nodes) => {
+                let mut roots = 0;
+                for node in &nodes {
+                    match node.node_type {
+                        NodeType::Element(_) => roots += 1,
+                        NodeType::Text(ref text) if !text.trim().is_empty() => {
+                            errors.push(ParseError {
+                                pos: node.span.map_or(0, |span| span.start),
+                                message: "text outside the root element".to_string(),
+                            })
+                        }
+                        NodeType::Text(_) | NodeType::Comment(_) => {}
+                    }
+                    if roots == 2 && node.element().is_some() {
+                        errors.push(ParseError {
+                            pos: node.span.map_or(0, |span| span.start),
+                            message: "more than one root element".to_string(),
+                        });
+                    }
+                }
+                if roots == 0 {
+                    errors.push(ParseError {
+                        pos: xml.len(),
+                        message: "no root element".to_string(),
+                    });
+                }
+            }
+            Err(error) => errors.push(error),
+        }
+        errors.sort_by_key(|error| error.pos);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Parses the CSS of every `<style>` element under `root` into one
+    /// stylesheet, in document order, so later `<style>` elements win ties
+    /// as they do in browsers. A `<style>` whose CSS doesn't parse is
+    /// skipped, as are those inside the inert contents of a `<template>`.
+    pub fn extract_stylesheets(root: &Node) -> Stylesheet {
+        let mut stylesheet = Stylesheet {
+            rules: Vec::new(),
+            at_rules: Vec::new(),
+            origin: Origin::Author,
+        };
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            if node.is_template() {
+                continue;
+            }
+            stack.extend(node.children.iter().rev());
+            let is_style = node
+                .element()
+                .is_some_and(|elem| elem.tag_name.eq_ignore_ascii_case("style"));
+            if !is_style {
+                continue;
+            }
+            if let Ok(parsed) = parse_css(&node.text_content()) {
+                stylesheet.append(parsed);
+            }
+        }
+        stylesheet
+    }
+
+    /// Parses a standalone, comma-separated selector list such as `ul > li, .item`.
+    fn parse_selector_list(source: &str) -> Result<Vec<Selector>, ParseError> {
+        let mut parser = Parser::new(source.to_string());
+        parser.consume_whitespace();
+        if parser.eof() {
+            return Err(parser.error("empty selector".to_string()));
+        }
+        let selectors = parser.parse_selectors()?;
+        if !parser.eof() {
+            return Err(parser.error("unexpected '{' in selector list".to_string()));
+        }
+        Ok(selectors)
+    }
+
+    pub type NodeId = usize;
+
+    /// A parent-aware view of a parsed tree. Nodes are stored in document
+    /// order in a flat arena, so the root is always `0`.
+    pub struct Dom<'a> {
+        nodes: Vec<DomNode<'a>>,
+    }
+
+    struct DomNode<'a> {
+        node: &'a Node,
+        parent: Option<NodeId>,
+        /// Position in the parent's children.
+        index: usize,
+        children: Vec<NodeId>,
+    }
+
+    impl<'a> Dom<'a> {
+        pub fn new(root: &'a Node) -> Dom<'a> {
+            let mut dom = Dom { nodes: Vec::new() };
+            dom.add(root, None, 0);
+            dom
+        }
+
+        fn add(&mut self, node: &'a Node, parent: Option<NodeId>, index: usize) -> NodeId {
+            let id = self.nodes.len();
+            self.nodes.push(DomNode {
+                node,
+                parent,
+                index,
+                children: Vec::new(),
+            });
+            for (index, child) in node.children.iter().enumerate() {
+                let child_id = self.add(child, Some(id), index);
+                self.nodes[id].children.push(child_id);
+            }
+            id
+        }
+
+        pub fn root(&self) -> NodeId {
+            0
+        }
+
+        pub fn node(&self, id: NodeId) -> &'a Node {
+            self.nodes[id].node
+        }
+
+        pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+            self.nodes[id].parent
+        }
+
+        pub fn children(&self, id: NodeId) -> &[NodeId] {
+            &self.nodes[id].children
+        }
+
+        /// The node's position among all of its parent's children, text and
+        /// comments included, counting from 0. The root is at 0.
+        pub fn sibling_index(&self, id: NodeId) -> usize {
+            self.nodes[id].index
+        }
+
+        /// How many children the node's parent has, the node included. The
+        /// root counts as its own only sibling.
+        pub fn sibling_count(&self, id: NodeId) -> usize {
+            self.parent(id)
+                .map_or(1, |parent| self.children(parent).len())
+        }
+
+        /// Iterates over the ancestors of a node, starting with its parent.
+        pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+            std::iter::successors(self.parent(id), move |&id| self.parent(id))
+        }
+
+        /// The text direction in effect for a node: the `dir` of the node
+        /// itself or of its nearest ancestor that sets one.
+        pub fn effective_dir(&self, id: NodeId) -> Option<&'a str> {
+            std::iter::once(id)
+                .chain(self.ancestors(id))
+                .find_map(|id| self.node(id).element().and_then(ElementData::dir))
+        }
+
+        /// Finds the nearest element, starting with the node itself and walking
+        /// up through its ancestors, that matches `selector`.
+        pub fn closest(&self, id: NodeId, selector: &str) -> Result<Option<NodeId>, ParseError> {
+            let selectors = parse_selector_list(selector)?;
+            Ok(std::iter::once(id).chain(self.ancestors(id)).find(|&id| {
+                match self.node(id).node_type {
+                    NodeType::Element(ref elem) => {
+                        let path = self.tree_path(id);
+                        let context = TreeContext { path: &path };
+                        selectors.iter().any(|s| matches(elem, Some(context), s))
+                    }
+                    NodeType::Text(_) | NodeType::Comment(_) => false,
+                }
+            }))
+        }
+
+        /// The sibling lists and indices leading from the root down to `id`,
+        /// as used for selector matching.
+        fn tree_path(&self, id: NodeId) -> Vec<(&'a [Node], usize)> {
+            let mut path: Vec<_> = std::iter::once(id)
+                .chain(self.ancestors(id))
+                .map(|id| match self.parent(id) {
+                    Some(parent) => (&self.node(parent).children[..], self.sibling_index(id)),
+                    None => (std::slice::from_ref(self.node(id)), 0),
+                })
+                .collect();
+            path.reverse();
+            path
+        }
+    }
+
+    /// The "what is this page" metadata usually found in `<head>`.
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct DocumentInfo {
+        pub title: Option<String>,
+        pub charset: Option<String>,
+        pub base_href: Option<String>,
+        pub lang: Option<String>,
+    }
+
+    /// Collects the title, charset, base URL and language of a document. Only
+    /// `<head>` is searched when there is one; fragments are searched whole.
+    pub fn document_info(root: &Node) -> DocumentInfo {
+        let head = root.first_by_tag("head").unwrap_or(root);
+        let mut info = DocumentInfo {
+            title: head
+                .first_by_tag("title")
+                .map(|title| title.text_content().trim().to_string()),
+            base_href: head
+                .first_by_tag("base")
+                .and_then(Node::element)
+                .and_then(|base| base.get_attribute("href"))
+                .map(str::to_string),
+            lang: root
+                .first_by_tag("html")
+                .and_then(Node::element)
+                .and_then(|html| html.get_attribute("lang"))
+                .map(str::to_string),
+            charset: None,
+        };
+        collect_charset(head, &mut info);
+        info
+    }
+
+    /// Parses just the `<head>` of a document, for reading metadata without
+    /// paying for the body. The head ends at `</head>`, at `<body>`, or at
+    /// the first thing that cannot be in a head: text, or any element other
+    /// than `base`, `link`, `meta`, `noscript`, `script`, `style`,
+    /// `template` and `title`. That is where `implied_structure` would start
+    /// the body too. A doctype and the `<html>` and `<head>` tags are skipped,
+    /// keeping the head's attributes, so this also works on documents that
+    /// leave them out.
+    pub fn parse_head_only(html: &str) -> Result<Node, ParseError> {
+        Parser::new(html.to_string()).parse_head()
+    }
+
+    /// Reads `<meta charset>` or the older
+    /// `<meta http-equiv="Content-Type" content="text/html; charset=...">`.
+    fn collect_charset(node: &Node, info: &mut DocumentInfo) {
+        if info.charset.is_some() {
+            return;
+        }
+        if let Some(meta) = node
+            .element()
+            .filter(|elem| elem.tag_name.eq_ignore_ascii_case("meta"))
+        {
+            let from_content = || {
+                let content = meta.get_attribute("content")?;
+                let lower = content.to_ascii_lowercase();
+                let start = lower.find("charset=")? + "charset=".len();
+                Some(content[start..].split(';').next()?.trim().to_string())
+            };
+            info.charset = meta
+                .get_attribute("charset")
+                .map(|charset| charset.trim().to_string())
+                .or_else(from_content);
+        }
+        for child in &node.children {
+            collect_charset(child, info);
+        }
+    }
+
+    /// Each `id` used by more than one element, with how many elements use
+    /// it, in the order the ids first appear.
+    pub fn duplicate_ids(root: &Node) -> Vec<(String, usize)> {
+        let mut counts: Vec<(String, usize)> = Vec::new();
+        let mut index: HashMap<&str, usize> = HashMap::new();
+        for id in root.elements().filter_map(ElementData::id) {
+            match index.get(id) {
+                Some(&i) => counts[i].1 += 1,
+                None => {
+                    index.insert(id, counts.len());
+                    counts.push((id.to_string(), 1));
+                }
+            }
+        }
+        counts.retain(|&(_, count)| count > 1);
+        counts
+    }
+
+    /// How many elements there are of each tag name, counting `root` itself.
+    /// Names are lowercased, so `<DIV>` and `<div>` count together; that
+    /// also merges SVG's `clipPath` with any `clippath`.
+    pub fn tag_histogram(root: &Node) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for elem in root.elements() {
+            *counts
+                .entry(elem.tag_name.to_ascii_lowercase())
+                .or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// A microdata item: an element with `itemscope` and the properties
+    /// its descendants give it with `itemprop`.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct MicrodataItem {
+        /// The `itemtype` URLs, such as `https://schema.org/Person`.
+        pub item_type: Vec<String>,
+        /// The `itemid`, if any.
+        pub item_id: Option<String>,
+        /// Each property in document order. A name can repeat, as with a
+        /// book's several authors.
+        pub properties: Vec<(String, MicrodataValue)>,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum MicrodataValue {
+        Text(String),
+        /// A property element that has `itemscope` itself.
+        Item(MicrodataItem),
+    }
+
+    /// Every top-level microdata item in the tree, in document order: those
+    /// whose `itemscope` element isn't itself a property of another item.
+    /// Items nested as properties appear inside their parent instead.
+    ///
+    /// A property's value is taken as the microdata spec says: `content` for
+    /// `<meta>`, `href` for links, `src` for images and other media, `data`
+    /// for `<object>`, `value` for `<data>` and `<meter>`, `datetime` for a
+    /// `<time>` that has one, and the text content otherwise. An element
+    /// with several names in `itemprop` gives the value to each. URLs are
+    /// returned as written, and `itemref` isn't followed.
+    pub fn extract_microdata(root: &Node) -> Vec<MicrodataItem> {
+        root.find(|elem| elem.has_attribute("itemscope") && !elem.has_attribute("itemprop"))
+            .into_iter()
+            .map(microdata_item)
+            .collect()
+    }
+
+    fn microdata_item(node: &Node) -> MicrodataItem {
+        let elem = node.element().expect("items are elements");
+        let mut item = MicrodataItem {
+            item_type: elem
+                .get_attribute("itemtype")
+                .unwrap_or("")
+                .split_ascii_whitespace()
+                .map(str::to_string)
+                .collect(),
+            item_id: elem.get_attribute("itemid").map(str::to_string),
+            properties: Vec::new(),
+        };
+        for child in &node.children {
+            collect_microdata_properties(child, &mut item.properties);
+        }
+        item
+    }
+
+    /// Adds the properties `node` and its descendants give to the enclosing
+    /// item, stopping at elements that start an item of their own.
+    fn collect_microdata_properties(node: &Node, properties: &mut Vec<(String, MicrodataValue)>) {
+        let Some(elem) = node.element() else {
+            return;
+        };
+        let scoped = elem.has_attribute("itemscope");
+        if let Some(names) = elem.get_attribute("itemprop") {
+            let value = if scoped {
+                MicrodataValue::Item(microdata_item(node))
+            } else {
+                MicrodataValue::Text(microdata_text(node, elem))
+            };
+            for name in names.split_ascii_whitespace() {
+                properties.push((name.to_string(), value.clone()));
+            }
+        }
+        if scoped {
+            return;
+        }
+        for child in &node.children {
+            collect_microdata_properties(child, properties);
+        }
+    }
+
+    fn microdata_text(node: &Node, elem: &ElementData) -> String {
+        let tag = elem.tag_name.to_ascii_lowercase();
+        let attribute = match tag.as_str() {
+            "meta" => "content",
+            "a" | "area" | "link" => "href",
+            "audio" | "embed" | "iframe" | "img" | "source" | "track" | "video" => "src",
+            "object" => "data",
+            "data" | "meter" => "value",
+            "time" if elem.has_attribute("datetime") => "datetime",
+            _ => return node.text_content(),
+        };
+        elem.get_attribute(attribute).unwrap_or("").to_string()
+    }
+
+    /// The text of each cell of a `<table>`, row by row, with whitespace
+    /// collapsed. Rows may sit directly in the table or in `<thead>`,
+    /// `<tbody>` and `<tfoot>`; tables nested inside cells are not rows of
+    /// this one. A cell spanning several columns or rows (`colspan`,
+    /// `rowspan`) has its text repeated in each position it covers, so rows
+    /// line up. Rows can still differ in length if the table is ragged.
+    pub fn extract_table(table: &Node) -> Vec<Vec<String>> {
+        let is_tag = |node: &Node, tags: &[&str]| {
+            node.element().is_some_and(|elem| {
+                tags.iter()
+                    .any(|tag| elem.tag_name.eq_ignore_ascii_case(tag))
+            })
+        };
+        let rows = table.children.iter().flat_map(|child| {
+            if is_tag(child, &["thead", "tbody", "tfoot"]) {
+                child.children.iter().collect()
+            } else {
+                vec![child]
+            }
+        });
+        // Cells from earlier rows still spanning down: column -> (rows left, text).
+        let mut spanning: HashMap<usize, (usize, String)> = HashMap::new();
+        let mut grid = Vec::new();
+        for row in rows.filter(|row| is_tag(row, &["tr"])) {
+            let mut cells = Vec::new();
+            for cell in row
+                .children
+                .iter()
+                .filter(|cell| is_tag(cell, &["td", "th"]))
+            {
+                fill_spanned_cells(&mut cells, &mut spanning);
+                let text = cell
+                    .text_content()
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let span = |name: &str, max: usize| {
+                    cell.element()
+                        .and_then(|elem| elem.get_attribute(name))
+                        .and_then(|value| value.trim().parse::<usize>().ok())
+                        .map_or(1, |span| span.clamp(1, max))
+                };
+                let rowspan = span("rowspan", 65_534);
+                for _ in 0..span("colspan", 1_000) {
+                    if rowspan > 1 {
+                        spanning.insert(cells.len(), (rowspan - 1, text.clone()));
+                    }
+                    cells.push(text.clone());
+                }
+            }
+            fill_spanned_cells(&mut cells, &mut spanning);
+            grid.push(cells);
+        }
+        grid
+    }
+
+    /// Fills the row's next columns with cells spanning down from above.
+    fn fill_spanned_cells(cells: &mut Vec<String>, spanning: &mut HashMap<usize, (usize, String)>) {
+        while let Some((rows_left, text)) = spanning.get_mut(&cells.len()) {
+            cells.push(text.clone());
+            *rows_left -= 1;
+            if *rows_left == 0 {
+                spanning.remove(&(cells.len() - 1));
+            }
+        }
+    }
+
+    /// Every `href` and `src` attribute value in the document, in document
+    /// order. With a `base`, each one is resolved against it by
+    /// `resolve_url`.
+    pub fn extract_urls(root: &Node, base: Option<&str>) -> Vec<String> {
+        let mut urls = Vec::new();
+        for elem in root.elements() {
+            for name in ["href", "src"] {
+                if let Some(url) = elem.get_attribute(name) {
+                    urls.push(match base {
+                        Some(base) => resolve_url(base, url),
+                        None => url.to_string(),
+                    });
+                }
+            }
+        }
+        urls
+    }
+
+    /// Resolves `href` against the absolute URL `base` the way a browser
+    /// follows a link: `//host/p` keeps the scheme, `/p` keeps the host,
+    /// `?q` and `#f` keep the path, and relative paths are joined onto the
+    /// base's directory with `.` and `..` segments removed. An `href` with its
+    /// own scheme is returned as is.
+    pub fn resolve_url(base: &str, href: &str) -> String {
+        let href = href.trim();
+        if url_scheme(href).is_some() {
+            return href.to_string();
+        }
+        let (origin, path, query, _) = split_url(base);
+        if let Some(rest) = href.strip_prefix("//") {
+            return match url_scheme(base) {
+                Some(scheme) => format!("{scheme}://{rest}"),
+                None => href.to_string(),
+            };
+        }
+        let (_, href_path, href_query, href_fragment) = split_url(href);
+        if href_path.is_empty() {
+            // `?q`, `#f` or an empty href only replace the later parts.
+            let query = if href_query.is_empty() {
+                query
+            } else {
+                href_query
+            };
+            return format!("{origin}{path}{query}{href_fragment}");
+        }
+        let path = if href_path.starts_with('/') {
+            href_path.to_string()
+        } else if path.is_empty() && !origin.is_empty() {
+            format!("/{href_path}")
+        } else {
+            let directory = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+            format!("{directory}{href_path}")
+        };
+        format!(
+            "{origin}{}{href_query}{href_fragment}",
+            remove_dot_segments(&path)
+        )
+    }
+
+    /// The scheme of an absolute URL such as `https:` or `mailto:`.
+    fn url_scheme(url: &str) -> Option<&str> {
+        let end = url.find(':')?;
+        let scheme = &url[..end];
+        let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+        valid.then_some(scheme)
+    }
+
+    /// Splits a URL into the scheme and host, the path, the `?query` and the
+    /// `#fragment`. Missing parts are empty.
+    fn split_url(url: &str) -> (&str, &str, &str, &str) {
+        let (rest, fragment) = url.find('#').map_or((url, ""), |i| url.split_at(i));
+        let (rest, query) = rest.find('?').map_or((rest, ""), |i| rest.split_at(i));
+        let mut origin_end = url_scheme(rest).map_or(0, |scheme| scheme.len() + 1);
+        if rest[origin_end..].starts_with("//") {
+            origin_end += 2;
+            origin_end += rest[origin_end..]
+                .find('/')
+                .unwrap_or(rest.len() - origin_end);
+        }
+        let (origin, path) = rest.split_at(origin_end);
+        (origin, path, query, fragment)
+    }
+
+    fn remove_dot_segments(path: &str) -> String {
+        let segments: Vec<&str> = path.split('/').collect();
+        let mut output: Vec<&str> = Vec::new();
+        for (i, &segment) in segments.iter().enumerate() {
+            let last = i + 1 == segments.len();
+            match segment {
+                "." | ".." => {
+                    if segment == ".." && output.len() > 1 {
+                        output.pop();
+                    }
+                    // `a/..` still names a directory.
+                    if last {
+                        output.push("");
+                    }
+                }
+                segment => output.push(segment),
+            }
+        }
+        output.join("/")
+    }
+
+    /// What `strip_presentational` takes out. Names are compared ignoring
+    /// case.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct StripOptions {
+        /// Attributes dropped from every element.
+        pub attributes: Vec<String>,
+        /// Elements removed along with their contents.
+        pub removed_tags: Vec<String>,
+        /// Elements replaced by their contents.
+        pub unwrapped_tags: Vec<String>,
+    }
+
+    impl Default for StripOptions {
+        /// Drops `style`, `class`, `id` and old presentational attributes
+        /// such as `bgcolor`, removes `<style>` elements, and unwraps
+        /// `<font>`, `<center>` and the like.
+        fn default() -> StripOptions {
+            let strings = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+            StripOptions {
+                attributes: strings(&[
+                    "align",
+                    "background",
+                    "bgcolor",
+                    "border",
+                    "class",
+                    "color",
+                    "face",
+                    "id",
+                    "style",
+                    "valign",
+                ]),
+                removed_tags: strings(&["style"]),
+                unwrapped_tags: strings(&[
+                    "basefont", "big", "blink", "center", "font", "marquee", "strike", "tt",
+                ]),
+            }
+        }
+    }
+
+    /// A copy of the tree without styling, for reader modes and content
+    /// extraction: see `StripOptions::default` for what goes. Text, comments
+    /// and the remaining structure are kept. `root` itself is never removed
+    /// or unwrapped, only stripped of attributes. Unwrapping can leave text
+    /// nodes side by side; `Node::normalize` merges them.
+    pub fn strip_presentational(root: &Node) -> Node {
+        strip_presentational_with(root, &StripOptions::default())
+    }
+
+    /// Like `strip_presentational`, removing what `options` lists instead.
+    pub fn strip_presentational_with(root: &Node, options: &StripOptions) -> Node {
+        let mut children = Vec::new();
+        for child in &root.children {
+            strip_into(child, options, &mut children);
+        }
+        strip_node(root, options, children)
+    }
+
+    fn strip_into(node: &Node, options: &StripOptions, out: &mut Vec<Node>) {
+        let listed = |names: &[String]| {
+            node.element().is_some_and(|elem| {
+                names
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(&elem.tag_name))
+            })
+        };
+        if listed(&options.removed_tags) {
+            return;
+        }
+        let mut children = Vec::new();
+        for child in &node.children {
+            strip_into(child, options, &mut children);
+        }
+        if listed(&options.unwrapped_tags) {
+            out.append(&mut children);
+        } else {
+            out.push(strip_node(node, options, children));
+        }
+    }
+
+    /// A copy of `node` with `children` and without the listed attributes.
+    fn strip_node(node: &Node, options: &StripOptions, children: Vec<Node>) -> Node {
+        let node_type = match node.node_type {
+            NodeType::Text(ref data) => NodeType::Text(data.clone()),
+            NodeType::Comment(ref data) => NodeType::Comment(data.clone()),
+            NodeType::Element(ref elem) => NodeType::Element(ElementData {
+                tag_name: Rc::clone(&elem.tag_name),
+                attributes: elem
+                    .attributes
+                    .iter()
+                    .filter(|(name, _)| {
+                        !options
+                            .attributes
+                            .iter()
+                            .any(|stripped| stripped.eq_ignore_ascii_case(name))
+                    })
+                    .map(|(name, value)| (Rc::clone(name), value.clone()))
+                    .collect(),
+                raw_attributes: None,
+            }),
+        };
+        Node {
+            children,
+            node_type,
+            span: node.span,
+        }
+    }
+
+    /// One step of a tree flattened by `to_tokens`.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum LayoutToken {
+        /// A start tag, with the attributes sorted by name.
+        Open(String, Vec<(String, String)>),
+        Text(String),
+        Close(String),
+    }
+
+    /// Flattens the tree into tokens in document order, for code that wants
+    /// a plain sequence rather than a recursive `Node`. Every `Open` has a
+    /// matching `Close`, void elements included, so nesting can be rebuilt
+    /// with a stack. Comments are left out.
+    pub fn to_tokens(root: &Node) -> Vec<LayoutToken> {
+        enum Step<'a> {
+            Visit(&'a Node),
+            /// After an element's children, where its `Close` goes.
+            Leave(&'a ElementData),
+        }
+        let mut tokens = Vec::new();
+        let mut stack = vec![Step::Visit(root)];
+        while let Some(step) = stack.pop() {
+            let node = match step {
+                Step::Visit(node) => node,
+                Step::Leave(elem) => {
+                    tokens.push(LayoutToken::Close(elem.tag_name.to_string()));
+                    continue;
+                }
+            };
+            match node.node_type {
+                NodeType::Element(ref elem) => {
+                    let mut attributes: Vec<(String, String)> = elem
+                        .attributes
+                        .iter()
+                        .map(|(name, value)| (name.to_string(), value.clone()))
+                        .collect();
+                    attributes.sort();
+                    tokens.push(LayoutToken::Open(elem.tag_name.to_string(), attributes));
+                    stack.push(Step::Leave(elem));
+                    stack.extend(node.children.iter().rev().map(Step::Visit));
+                }
+                NodeType::Text(ref text) => tokens.push(LayoutToken::Text(text.clone())),
+                NodeType::Comment(_) => {}
+            }
+        }
+        tokens
+    }
+
+    /// How many characters of a text node or comment `to_dot` shows.
+    const DOT_LABEL_CHARS: usize = 20;
+
+    /// Draws the tree as a GraphViz DOT graph, for `dot -Tpng`. Elements are
+    /// boxes labeled like a selector, `div#main.note`; text nodes show their
+    /// first characters, with whitespace-only ones left out, and comments
+    /// are dashed.
+    pub fn to_dot(root: &Node) -> String {
+        let mut dot = String::from("digraph dom {\n    node [fontname=\"monospace\"];\n");
+        let mut next_id = 0;
+        // Each node with the id of the graph node it hangs from.
+        let mut stack: Vec<(&Node, Option<usize>)> = vec![(root, None)];
+        while let Some((node, parent)) = stack.pop() {
+            let (label, attributes) = match node.node_type {
+                NodeType::Element(ref elem) => {
+                    let mut label = elem.tag_name.to_string();
+                    if let Some(id) = elem.id() {
+                        label.push('#');
+                        label.push_str(id);
+                    }
+                    for class in elem.class_tokens() {
+                        label.push('.');
+                        label.push_str(class);
+                    }
+                    (label, "shape=box")
+                }
+                NodeType::Text(ref text) if text.trim().is_empty() => continue,
+                NodeType::Text(ref text) => (dot_excerpt(text), "shape=plaintext"),
+                NodeType::Comment(ref data) => (dot_excerpt(data), "shape=note, style=dashed"),
+            };
+            let id = next_id;
+            next_id += 1;
+            dot.push_str(&format!(
+                "    n{} [label=\"{}\", {}];\n",
+                id,
+                dot_escape(&label),
+                attributes
+            ));
+            if let Some(parent) = parent {
+                dot.push_str(&format!("    n{} -> n{};\n", parent, id));
+            }
+            stack.extend(node.children.iter().rev().map(|child| (child, Some(id))));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The start of `text` with whitespace collapsed, cut to
+    /// `DOT_LABEL_CHARS` characters.
+    fn dot_excerpt(text: &str) -> String {
+        let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if collapsed.chars().count() <= DOT_LABEL_CHARS {
+            return collapsed;
+        }
+        let mut excerpt: String = collapsed.chars().take(DOT_LABEL_CHARS).collect();
+        excerpt.push_str("...");
+        excerpt
+    }
+
+    /// Escapes `label` for a double-quoted DOT string.
+    fn dot_escape(label: &str) -> String {
+        let mut escaped = String::with_capacity(label.len());
+        for c in label.chars() {
+            match c {
+                '"' | '\\' => {
+                    escaped.push('\\');
+                    escaped.push(c);
+                }
+                '\n' => escaped.push_str("\\n"),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    /// Renders a document as Markdown. Headings, paragraphs, `<strong>`/`<b>`,
+    /// `<em>`/`<i>`, links, lists, `<code>`, `<pre>` and `<blockquote>` map to
+    /// their Markdown forms; other elements contribute just their text, and
+    /// scripts, styles and templates are dropped.
+    ///
+    /// List items are kept tight, and a nested list is indented to line up
+    /// with its parent item's text. Inline formatting only applies to inline
+    /// content: a block element inside `<em>` is flattened into its text.
+    pub fn to_markdown(root: &Node) -> String {
+        let mut blocks = Vec::new();
+        markdown_blocks(std::slice::from_ref(root), &mut blocks);
+        blocks.join("\n\n")
+    }
+
+    /// Elements rendered as Markdown blocks rather than inline text.
+    const MARKDOWN_BLOCKS: &[&str] = &[
+        "address",
+        "article",
+        "aside",
+        "blockquote",
+        "body",
+        "div",
+        "footer",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "header",
+        "html",
+        "li",
+        "main",
+        "nav",
+        "ol",
+        "p",
+        "pre",
+        "section",
+        "ul",
+    ];
+
+    /// Elements with no Markdown rendering at all, not even their text.
+    const MARKDOWN_SKIPPED: &[&str] = &["head", "script", "style", "template"];
+
+    fn markdown_tag(node: &Node) -> Option<String> {
+        node.element()
+            .map(|elem| elem.tag_name.to_ascii_lowercase())
+    }
+
+    /// Appends the blocks for `nodes` to `blocks`. Runs of inline content
+    /// between block elements become paragraphs.
+    fn markdown_blocks(nodes: &[Node], blocks: &mut Vec<String>) {
+        let mut inline = String::new();
+        for node in nodes {
+            let tag = markdown_tag(node);
+            match tag.as_deref() {
+                Some(tag) if MARKDOWN_BLOCKS.contains(&tag) => {
+                    push_paragraph(&mut inline, blocks);
+                    markdown_block(node, tag, blocks);
+                }
+                _ => markdown_inline(node, &mut inline),
+            }
+        }
+        push_paragraph(&mut inline, blocks);
+    }
+
+    fn push_paragraph(inline: &mut String, blocks: &mut Vec<String>) {
+        let paragraph = inline.trim();
+        if !paragraph.is_empty() {
+            blocks.push(paragraph.to_string());
+        }
+        inline.clear();
+    }
+
+    fn markdown_block(node: &Node, tag: &str, blocks: &mut Vec<String>) {
+        match tag {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let mut text = String::new();
+                for child in &node.children {
+                    markdown_inline(child, &mut text);
+                }
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                if !text.is_empty() {
+                    let level = usize::from(tag.as_bytes()[1] - b'0');
+                    blocks.push(format!("{} {}", "#".repeat(level), text));
+                }
+            }
+            "pre" => {
+                // `<pre><code class="language-rust">` names the fence's language.
+                let language = node
+                    .children
+                    .iter()
+                    .filter_map(Node::element)
+                    .find(|elem| elem.tag_name.eq_ignore_ascii_case("code"))
+                    .and_then(|code| {
+                        code.classes()
+                            .into_iter()
+                            .find_map(|class| class.strip_prefix("language-"))
+                    })
+                    .unwrap_or("");
+                let code = node.text_content();
+                let code = code.strip_prefix('\n').unwrap_or(&code);
+                let code = code.strip_suffix('\n').unwrap_or(code);
+                let fence = if code.contains("```") { "~~~" } else { "```" };
+                blocks.push(format!("{fence}{language}\n{code}\n{fence}"));
+            }
+            "ul" | "ol" => {
+                let list = markdown_list(node, tag == "ol");
+                if !list.is_empty() {
+                    blocks.push(list);
+                }
+            }
+            "blockquote" => {
+                let mut inner = Vec::new();
+                markdown_blocks(&node.children, &mut inner);
+                if !inner.is_empty() {
+                    let quoted = inner
+                        .join("\n\n")
+                        .lines()
+                        .map(|line| {
+                            if line.is_empty() {
+                                ">".to_string()
+                            } else {
+                                format!("> {line}")
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    blocks.push(quoted);
+                }
+            }
+            _ => markdown_blocks(&node.children, blocks),
+        }
+    }
+
+    /// One line per `<li>`, numbered from 1 for `<ol>`. Everything after an
+    /// item's first line, nested lists included, is indented past its marker.
+    fn markdown_list(list: &Node, ordered: bool) -> String {
+        let items = list
+            .children
+            .iter()
+            .filter(|child| markdown_tag(child).as_deref() == Some("li"));
+        let mut lines = Vec::new();
+        for (index, item) in items.enumerate() {
+            let marker = if ordered {
+                format!("{}. ", index + 1)
+            } else {
+                "- ".to_string()
+            };
+            let mut blocks = Vec::new();
+            markdown_blocks(&item.children, &mut blocks);
+            let content = blocks.join("\n");
+            let indent = " ".repeat(marker.len());
+            let mut item_lines = content.lines();
+            lines.push(format!("{marker}{}", item_lines.next().unwrap_or("")));
+            lines.extend(item_lines.map(|line| {
+                if line.is_empty() {
+                    String::new()
+                } else {
+                    format!("{indent}{line}")
+                }
+            }));
+        }
+        lines.join("\n")
+    }
+
+    /// Appends `node` as inline Markdown, collapsing whitespace in text the
+    /// way a browser would.
+    fn markdown_inline(node: &Node, out: &mut String) {
+        match node.node_type {
+            NodeType::Text(ref text) => {
+                for c in text.chars() {
+                    if c.is_ascii_whitespace() {
+                        if !out.ends_with([' ', '\n']) {
+                            out.push(' ');
+                        }
+                    } else {
+                        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']') {
+                            out.push('\\');
+                        }
+                        out.push(c);
+                    }
+                }
+            }
+            NodeType::Comment(_) => {}
+            NodeType::Element(ref elem) => {
+                let tag = elem.tag_name.to_ascii_lowercase();
+                let inner = || {
+                    let mut inner = String::new();
+                    for child in &node.children {
+                        markdown_inline(child, &mut inner);
+                    }
+                    inner
+                };
+                match tag.as_str() {
+                    tag if MARKDOWN_SKIPPED.contains(&tag) => {}
+                    "strong" | "b" => wrap_inline(&inner(), "**", out),
+                    "em" | "i" => wrap_inline(&inner(), "*", out),
+                    "code" => {
+                        let code = node.text_content();
+                        if !code.is_empty() {
+                            out.push_str(&markdown_code_span(&code));
+                        }
+                    }
+                    "a" => match elem.get_attribute("href") {
+                        Some(href) => out.push_str(&format!(
+                            "[{}]({})",
+                            inner().trim(),
+                            markdown_link_destination(href)
+                        )),
+                        None => append_inline(&inner(), out),
+                    },
+                    "br" => out.push_str("\\\n"),
+                    _ => append_inline(&inner(), out),
+                }
+            }
+        }
+    }
+
+    /// `code` as a code span. The fence is a run of backticks of a length
+    /// that doesn't occur in `code`, and a space pads each side when the
+    /// code starts or ends with a backtick, or with a space on both sides,
+    /// since Markdown strips one such space.
+    fn markdown_code_span(code: &str) -> String {
+        let runs: HashSet<usize> = code
+            .split(|c| c != '`')
+            .map(str::len)
+            .filter(|&len| len > 0)
+            .collect();
+        let mut length = 1;
+        while runs.contains(&length) {
+            length += 1;
+        }
+        let fence = "`".repeat(length);
+        let pad = code.starts_with('`')
+            || code.ends_with('`')
+            || (code.starts_with(' ') && code.ends_with(' ') && code.trim() != "");
+        if pad {
+            format!("{fence} {code} {fence}")
+        } else {
+            format!("{fence}{code}{fence}")
+        }
+    }
+
+    /// `href` as a link destination. One with spaces, parentheses, angle
+    /// brackets, backslashes or control characters is wrapped in `<…>`,
+    /// escaping the brackets and backslashes and percent-encoding line
+    /// breaks.
+    fn markdown_link_destination(href: &str) -> String {
+        let plain = !href.is_empty()
+            && !href.chars().any(|c| {
+                c.is_whitespace() || c.is_control() || matches!(c, '(' | ')' | '<' | '>' | '\\')
+            });
+        if plain {
+            return href.to_string();
+        }
+        let mut destination = String::from("<");
+        for c in href.chars() {
+            match c {
+                '<' | '>' | '\\' => {
+                    destination.push('\\');
+                    destination.push(c);
+                }
+                '\n' => destination.push_str("%0A"),
+                '\r' => destination.push_str("%0D"),
+                c => destination.push(c),
+            }
+        }
+        destination.push('>');
+        destination
+    }
+
+    /// Appends rendered inline content without doubling the space between
+    /// two runs of text.
+    fn append_inline(inline: &str, out: &mut String) {
+        match inline.strip_prefix(' ') {
+            Some(rest) if out.is_empty() || out.ends_with([' ', '\n']) => out.push_str(rest),
+            _ => out.push_str(inline),
+        }
+    }
+
+    /// Wraps `inner` in `marker`, keeping surrounding whitespace outside the
+    /// markers since `** bold**` isn't bold in Markdown.
+    fn wrap_inline(inner: &str, marker: &str, out: &mut String) {
+        let trimmed = inner.trim();
+        if trimmed.is_empty() {
+            out.push_str(inner);
+            return;
+        }
+        if inner.starts_with(' ') {
+            append_inline(" ", out);
+        }
+        out.push_str(&format!("{marker}{trimmed}{marker}"));
+        if inner.ends_with(' ') {
+            out.push(' ');
+        }
+    }
+
+    /// Reformats HTML: every block-level element starts on its own line,
+    /// indented two spaces per level, while text and inline elements such as
+    /// `<b>` and `<a>` stay together on one line with whitespace collapsed.
+    /// `<pre>`, `<textarea>`, `<script>` and `<style>` keep their content
+    /// exactly. Attributes are written in name order, one space apart.
+    pub fn tidy(html: &str) -> Result<String, ParseError> {
+        let root = source(html.to_string())?;
+        let mut out = Vec::new();
+        write_tidy(&root, 0, &mut out).expect("writing to a Vec cannot fail");
+        Ok(String::from_utf8(out).expect("serialized HTML is valid UTF-8"))
+    }
+
+    /// Elements `tidy` keeps on the line of the surrounding text.
+    const INLINE_ELEMENTS: &[&str] = &[
+        "a", "abbr", "b", "bdi", "bdo", "br", "button", "cite", "code", "data", "dfn", "em", "i",
+        "img", "input", "kbd", "label", "mark", "q", "s", "samp", "select", "small", "span",
+        "strong", "sub", "sup", "time", "u", "var", "wbr",
+    ];
+
+    /// Elements whose content `tidy` leaves exactly as parsed.
+    const VERBATIM_ELEMENTS: &[&str] = &["pre", "script", "style", "textarea"];
+
+    fn is_inline(node: &Node) -> bool {
+        node.element().is_none_or(|elem| {
+            INLINE_ELEMENTS
+                .iter()
+                .any(|tag| tag.eq_ignore_ascii_case(&elem.tag_name))
+        })
+    }
+
+    fn write_tidy<W: Write>(node: &Node, depth: usize, w: &mut W) -> io::Result<()> {
+        let indent = "  ".repeat(depth);
+        let Some(elem) = node.element().filter(|_| !is_inline(node)) else {
+            return writeln!(w, "{}{}", indent, tidy_inline(std::slice::from_ref(node)));
+        };
+        write!(w, "{}", indent)?;
+        write_start_tag(w, elem)?;
+        if is_void_element(elem) && node.children.is_empty() {
+            return writeln!(w);
+        }
+        let verbatim = VERBATIM_ELEMENTS
+            .iter()
+            .any(|tag| tag.eq_ignore_ascii_case(&elem.tag_name));
+        if verbatim {
+            for child in &node.children {
+                child.write_html(w)?;
+            }
+        } else if node.children.iter().all(is_inline) {
+            write!(w, "{}", tidy_inline(&node.children).trim())?;
+        } else {
+            writeln!(w)?;
+            let child_indent = "  ".repeat(depth + 1);
+            let mut run_start = 0;
+            for (i, child) in node.children.iter().enumerate() {
+                if is_inline(child) {
+                    continue;
+                }
+                let run = tidy_inline(&node.children[run_start..i]);
+                if !run.trim().is_empty() {
+                    writeln!(w, "{}{}", child_indent, run.trim())?;
+                }
+                write_tidy(child, depth + 1, w)?;
+                run_start = i + 1;
+            }
+            let run = tidy_inline(&node.children[run_start..]);
+            if !run.trim().is_empty() {
+                writeln!(w, "{}{}", child_indent, run.trim())?;
+            }
+            write!(w, "{}", indent)?;
+        }
+        writeln!(w, "</{}>", elem.tag_name)
+    }
+
+    /// Serializes inline content on one line, collapsing runs of whitespace
+    /// in text to single spaces.
+    fn tidy_inline(nodes: &[Node]) -> String {
+        let mut html = String::new();
+        for node in nodes {
+            match node.node_type {
+                NodeType::Text(ref data) => {
+                    let mut escaped = Vec::new();
+                    write_escaped(&mut escaped, data, false).expect("writing to a Vec cannot fail");
+                    let escaped = String::from_utf8(escaped).expect("escaped text is valid UTF-8");
+                    for c in escaped.chars() {
+                        if !c.is_ascii_whitespace() {
+                            html.push(c);
+                        } else if !html.ends_with(' ') {
+                            html.push(' ');
+                        }
+                    }
+                }
+                NodeType::Comment(_) => html.push_str(&node.to_html()),
+                NodeType::Element(ref elem) => {
+                    let mut start_tag = Vec::new();
+                    write_start_tag(&mut start_tag, elem).expect("writing to a Vec cannot fail");
+                    html.push_str(&String::from_utf8(start_tag).expect("tags are valid UTF-8"));
+                    if is_void_element(elem) && node.children.is_empty() {
+                        continue;
+                    }
+                    html.push_str(&tidy_inline(&node.children));
+                    html.push_str(&format!("</{}>", elem.tag_name));
+                }
+            }
+        }
+        html
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use proptest::prelude::*;
+
+        /// The text of each element `selector` matches, in document order.
+        fn matched_text(root: &Node, selector: &str) -> Vec<String> {
+            root.query_selector_all(selector)
+                .unwrap()
+                .iter()
+                .map(|node| node.text_content())
+                .collect()
+        }
+
+        #[test]
+        fn nth_child_odd() {
+            let root =
+                source("<ul><li>1</li><li>2</li><li>3</li><li>4</li><li>5</li></ul>".to_string())
+                    .unwrap();
+            assert_eq!(matched_text(&root, "li:nth-child(odd)"), ["1", "3", "5"]);
+            assert_eq!(matched_text(&root, "li:nth-child(2n+1)"), ["1", "3", "5"]);
+        }
+
+        #[test]
+        fn nth_child_extreme_offsets() {
+            let root = source("<ul><li>1</li><li>2</li></ul>".to_string()).unwrap();
+            assert!(matched_text(&root, "li:nth-child(-2147483648)").is_empty());
+            assert!(matched_text(&root, "li:nth-child(-n-2147483648)").is_empty());
+            assert_eq!(matched_text(&root, "li:nth-child(2147483647n+2)"), ["2"]);
+        }
+
+        #[test]
+        fn font_longhands() {
+            let root = source("<p>x</p>".to_string()).unwrap();
+            let css = parse_css(
+                "p { font-size: 12px; font-family: 'Helvetica Neue', Arial, serif; \
+                 font-weight: 700; font-style: italic; }",
+            )
+            .unwrap();
+            let styled = style_tree(&root, &[css]);
+            assert_eq!(
+                styled.font(),
+                Some(Font {
+                    style: FontStyle::Italic,
+                    weight: 700,
+                    size: Value::Length(12.0, Unit::Px),
+                    family: vec![
+                        "Helvetica Neue".to_string(),
+                        "Arial".to_string(),
+                        "serif".to_string()
+                    ],
+                })
+            );
+        }
+
+        #[test]
+        fn font_family_keeps_case() {
+            let root = source("<p>x</p>".to_string()).unwrap();
+            let css = parse_css("p { font: bold 10px Arial; font-family: Georgia; }").unwrap();
+            let font = style_tree(&root, &[css]).font().unwrap();
+            assert_eq!(font.family, ["Georgia"]);
+            assert_eq!(font.weight, 700);
+        }
+
+        #[test]
+        fn font_shorthand() {
+            let root = source("<p>x</p>".to_string()).unwrap();
+            let css =
+                parse_css(r#"p { font: italic 600 1.5em/2 "Fira Sans", sans-serif; }"#).unwrap();
+            let font = style_tree(&root, &[css]).font().unwrap();
+            assert_eq!(font.style, FontStyle::Italic);
+            assert_eq!(font.weight, 600);
+            assert_eq!(font.size, Value::Length(1.5, Unit::Em));
+            assert_eq!(font.family, ["Fira Sans", "sans-serif"]);
+            let css = parse_css("p { font: caption; }").unwrap();
+            assert_eq!(style_tree(&root, &[css]).font(), None);
+        }
+
+        #[test]
+        fn numeric_references_to_invalid_code_points() {
+            let none = HashMap::new();
+            assert_eq!(decode_entities("a&#0;b", &none), "a\u{fffd}b");
+            assert_eq!(decode_entities("&#xD800;", &none), "\u{fffd}");
+            assert_eq!(
+                decode_entities("&#x110000;&#99999999999;", &none),
+                "\u{fffd}\u{fffd}"
+            );
+            assert_eq!(
+                decode_entities("&#233;&#xE9;&eacute;", &none),
+                "\u{e9}\u{e9}\u{e9}"
+            );
+            assert_eq!(decode_entities("&#;&#x;&#12a;", &none), "&#;&#x;&#12a;");
+        }
+
+        #[test]
+        fn ampersands_far_from_a_semicolon() {
+            let none = HashMap::new();
+            let text = format!("{};", "&".repeat(200_000));
+            let start = Instant::now();
+            assert_eq!(decode_entities(&text, &none), text);
+            assert!(start.elapsed() < Duration::from_secs(1));
+            let custom = HashMap::from([("my-ent".to_string(), "x".to_string())]);
+            assert_eq!(decode_entities("&my-ent; & a;", &custom), "x & a;");
+        }
+
+        #[test]
+        fn tidy_keeps_non_breaking_spaces() {
+            let tidied = tidy("<div><p>a&nbsp;&nbsp;b   c</p></div>").unwrap();
+            assert!(tidied.contains("<p>a\u{a0}\u{a0}b c</p>"), "{}", tidied);
+        }
+
+        #[test]
+        fn markdown_keeps_non_breaking_spaces() {
+            let root = source("<p>a&nbsp;&nbsp;b   c</p>".to_string()).unwrap();
+            assert_eq!(to_markdown(&root).trim(), "a\u{a0}\u{a0}b c");
+        }
+
+        #[test]
+        fn parse_lossy_keeps_going_after_errors() {
+            let (root, errors) = parse_lossy("<div a=b>x</div><p c=\"d>y");
+            let div = root.query_selector("div").unwrap().unwrap();
+            assert_eq!(div.element().unwrap().get_attribute("a"), Some("b"));
+            assert_eq!(div.text_content(), "x");
+            let p = root.query_selector("p").unwrap().unwrap();
+            assert_eq!(p.element().unwrap().get_attribute("c"), Some("d>y"));
+            let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+            assert_eq!(
+                messages,
+                [
+                    "unquoted attribute value",
+                    "unterminated attribute value",
+                    "unterminated start tag"
+                ]
+            );
+        }
+
+        #[test]
+        fn parse_lossy_steps_past_every_error() {
+            let (root, errors) = parse_lossy("<a / href='x'>1</a><!-- open <b>2</b>");
+            assert_eq!(
+                root.query_selector("a").unwrap().unwrap().text_content(),
+                "1"
+            );
+            assert_eq!(errors.len(), 2);
+            let deep = format!("{}x", "<div>".repeat(600));
+            let (root, errors) = parse_lossy(&deep);
+            assert_eq!(root.text_content(), "x");
+            assert!(errors[0]
+                .message
+                .starts_with("elements nested more than 512 deep"));
+        }
+
+        #[test]
+        fn nth_child_and_nth_of_type_on_mixed_siblings() {
+            let root =
+                source("<div><h2>h1</h2><p>p1</p><p>p2</p><h2>h2</h2><p>p3</p></div>".to_string())
+                    .unwrap();
+            assert_eq!(matched_text(&root, "p:nth-child(2)"), ["p1"]);
+            assert_eq!(matched_text(&root, "p:nth-of-type(2)"), ["p2"]);
+            assert_eq!(matched_text(&root, "p:first-child"), Vec::<String>::new());
+            assert_eq!(matched_text(&root, "p:first-of-type"), ["p1"]);
+            assert_eq!(matched_text(&root, "h2:last-of-type"), ["h2"]);
+            assert_eq!(matched_text(&root, "p:last-child"), ["p3"]);
+            assert_eq!(matched_text(&root, ":nth-of-type(odd)"), ["h1", "p1", "p3"]);
+        }
+
+        #[test]
+        fn collapse_whitespace_keeps_non_breaking_spaces() {
+            let parser = ParserBuilder::new().collapse_whitespace(true);
+            let root = parser.parse("<p>a&nbsp;&nbsp;b</p>".to_string()).unwrap();
+            assert_eq!(root.text_content(), "a\u{a0}\u{a0}b");
+            let root = parser.parse("<p>a   b</p>".to_string()).unwrap();
+            assert_eq!(root.text_content(), "a b");
+            let root = parser.parse("<p>a&#32;&#32; b</p>".to_string()).unwrap();
+            assert_eq!(root.text_content(), "a b");
+        }
+
+        #[test]
+        fn custom_element_names() {
+            let root = source("<my-widget></my-widget>".to_string()).unwrap();
+            assert_eq!(root.element().unwrap().tag_name(), "my-widget");
+            let root = source("<x-foo><my_el>x</my_el></x-foo>".to_string()).unwrap();
+            assert_eq!(matched_text(&root, "x-foo > my_el"), ["x"]);
+            assert!(source("<1abc></1abc>".to_string()).is_err());
+        }
+
+        #[test]
+        fn attribute_quoting() {
+            let root =
+                source(r#"<a title="it's fine" alt='say "hi"' rel=''></a>"#.to_string()).unwrap();
+            let a = root.element().unwrap();
+            assert_eq!(a.get_attribute("title"), Some("it's fine"));
+            assert_eq!(a.get_attribute("alt"), Some(r#"say "hi""#));
+            assert_eq!(a.get_attribute("rel"), Some(""));
+            assert!(source(r#"<a title="never closed"#.to_string()).is_err());
+            assert!(source("<a title='never closed></a>".to_string()).is_err());
+        }
+
+        #[test]
+        fn inner_and_outer_html() {
+            let html = r#"<div id="a"><p>x &amp; <b>y</b></p><!--c--><span></span></div>"#;
+            let root = source(html.to_string()).unwrap();
+            assert_eq!(root.outer_html(), html);
+            assert_eq!(
+                root.inner_html(),
+                "<p>x &amp; <b>y</b></p><!--c--><span></span>"
+            );
+            let p = &root.children[0];
+            assert_eq!(p.outer_html(), "<p>x &amp; <b>y</b></p>");
+            assert_eq!(p.inner_html(), "x &amp; <b>y</b>");
+            assert_eq!(p.children[0].inner_html(), "x &amp; ");
+            let reparsed = source(format!("<div>{}</div>", root.inner_html())).unwrap();
+            assert_eq!(reparsed.inner_html(), root.inner_html());
+            assert_eq!(source(root.outer_html()).unwrap().outer_html(), html);
+        }
+
+        #[test]
+        fn lowercase_tags_keeps_foreign_case() {
+            let parser = ParserBuilder::new().lowercase_tags(true);
+            let root = parser
+                .parse("<DIV><svg><linearGradient></linearGradient></svg></DIV>".to_string())
+                .unwrap();
+            assert_eq!(root.element().unwrap().tag_name(), "div");
+            let svg = &root.children[0];
+            assert_eq!(
+                svg.children[0].element().unwrap().tag_name(),
+                "linearGradient"
+            );
+        }
+
+        #[test]
+        fn has_attribute_present_and_absent() {
+            let root = source(r#"<input disabled value="">"#.to_string()).unwrap();
+            let input = root.element().unwrap();
+            assert!(input.has_attribute("disabled"));
+            assert_eq!(input.get_attribute("disabled"), Some(""));
+            assert!(input.has_attribute("value"));
+            assert!(!input.has_attribute("checked"));
+            assert_eq!(input.get_attribute("checked"), None);
+        }
+
+        #[test]
+        fn self_closing_foreign_elements() {
+            let root = source("<svg><rect/></svg>".to_string()).unwrap();
+            assert_eq!(root.children.len(), 1);
+            let rect = &root.children[0];
+            assert_eq!(rect.element().unwrap().tag_name(), "rect");
+            assert!(rect.children.is_empty());
+            let root = source(r#"<svg><circle r="5"/><g/></svg>"#.to_string()).unwrap();
+            assert_eq!(root.children.len(), 2);
+            assert!(source("<div><span/></div>".to_string()).is_err());
+        }
+
+        #[test]
+        fn empty_strict_and_lenient() {
+            let empty = |root: &Node| root.query_selector_all("div:empty").unwrap().len();
+            let blank = |root: &Node| root.query_selector_all("div:blank").unwrap().len();
+            let root = source("<main><div></div></main>".to_string()).unwrap();
+            assert_eq!((empty(&root), blank(&root)), (1, 1));
+            let root = source("<main><div><!--c--></div></main>".to_string()).unwrap();
+            assert_eq!((empty(&root), blank(&root)), (1, 1));
+            // Built by hand, since parsing drops whitespace before a node.
+            let div = elem("div", HashMap::new(), vec![text(" ".to_string())]);
+            let root = elem("main", HashMap::new(), vec![div]);
+            assert_eq!((empty(&root), blank(&root)), (0, 1));
+            let root = source("<main><div>x</div></main>".to_string()).unwrap();
+            assert_eq!((empty(&root), blank(&root)), (0, 0));
+        }
+
+        #[test]
+        fn query_selector_all_with_combinators() {
+            let root = source(
+                "<div><ul><li>1</li><li>2<ol><li>3</li></ol></li></ul>\
+                 <ol><li>4</li></ol><p>a</p><span>b</span><span>c</span></div>"
+                    .to_string(),
+            )
+            .unwrap();
+            assert_eq!(matched_text(&root, "ul > li"), ["1", "23"]);
+            assert_eq!(matched_text(&root, "ul li"), ["1", "23", "3"]);
+            assert_eq!(matched_text(&root, "p + span"), ["b"]);
+            assert_eq!(matched_text(&root, "p ~ span"), ["b", "c"]);
+            assert_eq!(matched_text(&root, "div > ol > li"), ["4"]);
+        }
+
+        #[test]
+        fn rename_tag_in_nested_tree() {
+            let mut root = source(
+                r#"<B class="x"><p>a <b>b <i><b>c</b></i></b></p><bold>d</bold></B>"#.to_string(),
+            )
+            .unwrap();
+            root.rename_tag("b", "strong");
+            assert_eq!(
+                root.to_html(),
+                r#"<strong class="x"><p>a <strong>b <i><strong>c</strong></i></strong></p><bold>d</bold></strong>"#
+            );
+        }
+
+        #[test]
+        fn dataset_camel_case() {
+            let root = source(
+                r#"<div data-user-id="7" data-x="a" data-a-b-c="b" data--y="c" data-z-="d" data-Up="e" id="f"></div>"#
+                    .to_string(),
+            )
+            .unwrap();
+            let dataset = root.element().unwrap().dataset();
+            assert_eq!(
+                dataset,
+                HashMap::from([
+                    ("userId".to_string(), "7"),
+                    ("x".to_string(), "a"),
+                    ("aBC".to_string(), "b"),
+                    ("Y".to_string(), "c"),
+                    ("z-".to_string(), "d"),
+                ])
+            );
+        }
+
+        #[test]
+        fn index_based_child_apis_out_of_bounds() {
+            let mut root = source("<ul><li>1</li><li>2</li></ul>".to_string()).unwrap();
+            let Err(rejected) = root.insert_child_at(3, text("x".to_string())) else {
+                panic!("inserted past the end");
+            };
+            assert_eq!(rejected.text_content(), "x");
+            assert!(root.remove_child(2).is_none());
+            assert!(root.remove_child(usize::MAX).is_none());
+            assert_eq!(root.children.len(), 2);
+            assert!(root.insert_child_at(2, text("end".to_string())).is_ok());
+            assert!(root.insert_child_at(0, text("start".to_string())).is_ok());
+            assert_eq!(root.text_content(), "start12end");
+            assert_eq!(root.remove_child(3).unwrap().text_content(), "end");
+            assert_eq!(root.remove_child(0).unwrap().text_content(), "start");
+            let mut leaf = text("t".to_string());
+            assert!(leaf.remove_child(0).is_none());
+            assert!(leaf.insert_child_at(1, text("u".to_string())).is_err());
+        }
+
+        #[test]
+        fn specificity_of_not() {
+            let specificity = |source| Selector::parse(source).unwrap().specificity();
+            assert_eq!(specificity("div:not(.a)#b"), (1, 1, 1));
+            assert_eq!(specificity(":not(#a)"), (1, 0, 0));
+            assert_eq!(specificity("div:not(span)"), (0, 0, 2));
+            assert_eq!(specificity("a:not([href]):checked"), (0, 2, 1));
+            assert_eq!(specificity("li:first-child:not(.x.y)"), (0, 3, 1));
+        }
+
+        #[test]
+        fn attribute_operators() {
+            let root = source(
+                r#"<div><a lang="en">1</a><a lang="en-US">2</a><a lang="english">3</a><a lang="EN">4</a>
+                <b class="btn primary">5</b><b class="btn-lg">6</b><b class="xbtn">7</b></div>"#
+                    .to_string(),
+            )
+            .unwrap();
+            assert_eq!(matched_text(&root, "[lang]"), ["1", "2", "3", "4"]);
+            assert_eq!(matched_text(&root, r#"[lang="en"]"#), ["1"]);
+            assert_eq!(matched_text(&root, r#"[lang|="en"]"#), ["1", "2"]);
+            assert_eq!(matched_text(&root, r#"[lang^="en"]"#), ["1", "2", "3"]);
+            assert_eq!(matched_text(&root, r#"[lang$="sh"]"#), ["3"]);
+            assert_eq!(matched_text(&root, r#"[lang*="n-U"]"#), ["2"]);
+            assert_eq!(matched_text(&root, r#"[class~="btn"]"#), ["5"]);
+            assert_eq!(matched_text(&root, r#"[class~="primary"]"#), ["5"]);
+            assert_eq!(matched_text(&root, r#"[class*="btn"]"#), ["5", "6", "7"]);
+            assert!(matched_text(&root, r#"[class~="btn primary"]"#).is_empty());
+        }
+
+        #[test]
+        fn literal_less_than_in_text() {
+            let parser = ParserBuilder::new().recover(true);
+            let root = parser.parse("<p>a < b</p>".to_string()).unwrap();
+            assert_eq!(root.text_content(), "a < b");
+            assert_eq!(root.to_html(), "<p>a &lt; b</p>");
+            let root = parser.parse("<p>5 <10 and x<</p>".to_string()).unwrap();
+            assert_eq!(root.text_content(), "5 <10 and x<");
+            assert!(source("<p>a < b</p>".to_string()).is_err());
+        }
+
+        #[test]
+        fn closing_tag_case() {
+            let root = source("<DIV></div>".to_string()).unwrap();
+            assert_eq!(root.element().unwrap().tag_name(), "DIV");
+            let root = source("<Ul><li>1</LI></uL>".to_string()).unwrap();
+            assert_eq!(matched_text(&root, "li"), ["1"]);
+            assert!(validate_xml("<DIV></div>").is_err());
+            assert!(validate_xml("<DIV></DIV>").is_ok());
+        }
+
+        #[test]
+        fn values_the_parser_does_not_model_are_kept_as_written() {
+            let value = |declaration: &str| {
+                let css = parse_css(&format!("p {{ {} }}", declaration)).unwrap();
+                let declaration = &css.rules[0].declarations[0];
+                (declaration.value.clone(), declaration.raw.clone())
+            };
+            let keyword = |text: &str| Value::Keyword(text.to_string());
+            assert_eq!(value("margin: 0;").0, Value::Length(0.0, Unit::Px));
+            assert_eq!(value("line-height: 1.5").0, keyword("1.5"));
+            assert_eq!(value("border: 1px solid red").0, keyword("1px solid red"));
+            assert_eq!(
+                value("transition: all 0.3s ease !important"),
+                (keyword("all 0.3s ease"), "all 0.3s ease".to_string())
+            );
+            assert_eq!(value("color: rgb(0, 0, 0);").0, keyword("rgb(0, 0, 0)"));
+            assert_eq!(
+                value("color: #fff").0,
+                Value::ColorValue(Color {
+                    r: 255,
+                    g: 255,
+                    b: 255,
+                    a: 255
+                })
+            );
+            assert_eq!(
+                value("color: #ff000080").0,
+                Value::ColorValue(Color {
+                    r: 255,
+                    g: 0,
+                    b: 0,
+                    a: 128
+                })
+            );
+            assert_eq!(value("width: .5em").0, Value::Length(0.5, Unit::Em));
+            assert!(parse_css("p { color: ; }").is_err());
+        }
+
+        #[test]
+        fn extract_stylesheets_from_real_style_blocks() {
+            let root = source(
+                "<div><style>p { margin: 0; border: 1px solid #ccc; line-height: 1.5 }\
+                 a { transition: all 0.3s ease; }</style></div>"
+                    .to_string(),
+            )
+            .unwrap();
+            let stylesheet = extract_stylesheets(&root);
+            assert_eq!(stylesheet.rules.len(), 2);
+            assert_eq!(stylesheet.rules[0].declarations.len(), 3);
+        }
+
+        #[test]
+        fn media_rules_apply_in_source_order() {
+            let root = source("<div><p>x</p></div>".to_string()).unwrap();
+            let display = |css: &str| {
+                let styled = style_tree(&root, &[parse_css(css).unwrap()]);
+                styled.children[0].value("display").cloned()
+            };
+            let keyword = |text: &str| Some(Value::Keyword(text.to_string()));
+            assert_eq!(
+                display("@media screen { p { display: none } }"),
+                keyword("none")
+            );
+            assert_eq!(
+                display("@media screen { @supports (x: y) { div p { display: none } } }"),
+                keyword("none")
+            );
+            assert_eq!(
+                display("p { display: block } @media print { p { display: none } }"),
+                keyword("none")
+            );
+            assert_eq!(
+                display("@media print { p { display: none } } p { display: block }"),
+                keyword("block")
+            );
+        }
+
+        #[test]
+        fn at_rules_keep_their_place_when_written() {
+            let css = parse_css("p{color:red}@media print{p{color:blue}}a{color:red}").unwrap();
+            assert_eq!(
+                css.minify(),
+                "p{color:red}@media print{p{color:blue}}a{color:red}"
+            );
+            assert_eq!(
+                css.to_string(),
+                "p { color: red; }\n@media print {p{color:blue}}\na { color: red; }\n"
+            );
+            let css =
+                parse_css("@import 'a.css'; p { color: red } @media print { b { x: y } }").unwrap();
+            assert_eq!(parse_css(&css.minify()).unwrap().minify(), css.minify());
+            let mut css = parse_css("@import 'a.css'; @media print { p { color: blue } }").unwrap();
+            css.load_imports(|_| Some("p { color: red } @font-face { x: y }".to_string()))
+                .unwrap();
+            assert_eq!(
+                css.minify(),
+                "p{color:red}@font-face{x: y}@media print{p{color:blue}}"
+            );
+        }
+
+        #[test]
+        fn load_imports_leaves_the_sheet_alone_on_error() {
+            let source =
+                "@import 'good.css'; @import 'bad.css'; @media print { p { x: y } } a { x: y }";
+            let mut css = parse_css(source).unwrap();
+            let before = css.to_string();
+            let loader = |url: &str| match url {
+                "good.css" => Some("b { x: y }".to_string()),
+                _ => Some("p { color: }".to_string()),
+            };
+            assert!(css.load_imports(loader).is_err());
+            assert_eq!(css.to_string(), before);
+            assert_eq!(css.at_rules().len(), 3);
+            let mut css = parse_css("@import 'a.css'; a { x: y }").unwrap();
+            let nested = |url: &str| match url {
+                "a.css" => Some("@import 'b.css'; b { x: y }".to_string()),
+                _ => Some("{".to_string()),
+            };
+            assert!(css.load_imports(nested).is_err());
+            assert_eq!(css.minify(), "@import 'a.css';a{x:y}");
+        }
+
+        /// Each node's HTML and span, to compare trees parsed differently.
+        fn html_and_spans(root: &Node) -> Vec<(String, Option<Span>)> {
+            root.walk()
+                .map(|(_, node)| (node.to_html(), node.span()))
+                .collect()
+        }
+
+        #[test]
+        fn chunked_parsing_matches_whole_parsing_at_every_split() {
+            let documents = [
+                "<!DOCTYPE html><!-- a comment --><p class=\"x\">a &amp; b</p>\
+                 <script>if (a < b) {}</script><li>1<li>2<div>c&eacute;</div>",
+                "<b>1<i>2</b>3</i><p>x<p>y",
+            ];
+            for (document, recover) in documents.into_iter().zip([false, true]) {
+                let builder = ParserBuilder::new().recover(recover);
+                let whole = html_and_spans(&builder.parse(document.to_string()).unwrap());
+                for split in 0..=document.len() {
+                    let mut chunked = builder.chunked();
+                    chunked.feed(&document[..split]).unwrap();
+                    chunked.feed(&document[split..]).unwrap();
+                    assert_eq!(chunked.len(), document.len());
+                    let parsed = html_and_spans(&chunked.finish().unwrap());
+                    assert_eq!(parsed, whole, "split at {}", split);
+                }
+                let mut chunked = builder.chunked();
+                for c in document.chars() {
+                    chunked.feed(c.encode_utf8(&mut [0; 4])).unwrap();
+                }
+                assert_eq!(html_and_spans(&chunked.finish().unwrap()), whole);
+            }
+        }
+
+        #[test]
+        fn chunked_parsing_keeps_only_the_unparsed_input() {
+            let mut chunked = ParserBuilder::new().chunked();
+            chunked.feed("<p>one</p><p>two</p><!-- thr").unwrap();
+            assert_eq!(chunked.nodes.len(), 1);
+            assert_eq!(chunked.parser.input, "<p>two</p><!-- thr");
+            chunked.feed("ee --><scr").unwrap();
+            assert_eq!(chunked.nodes.len(), 2);
+            assert_eq!(chunked.parser.input, "<!-- three --><scr");
+            chunked.feed("ipt>a</scr").unwrap();
+            chunked.feed("ipt><p>&am").unwrap();
+            chunked.feed("p;</p>").unwrap();
+            assert_eq!(chunked.len(), 64);
+            let root = chunked.finish().unwrap();
+            assert_eq!(
+                root.to_html(),
+                "<html><p>one</p><p>two</p><!-- three --><script>a</script><p>&amp;</p></html>"
+            );
+            let mut chunked = ParserBuilder::new().chunked();
+            chunked.feed("<p>a</p></div>").unwrap();
+            assert_eq!(chunked.finish().err().map(|error| error.pos), Some(8));
+        }
+
+        #[test]
+        fn reparse_matches_a_full_parse() {
+            // Renaming the `<em>`, which lies outside every edit, marks the
+            // old tree: a full parse loses the mark, an incremental one
+            // keeps it.
+            let check = |builder: &ParserBuilder, old: &str, from: &str, to: &str, full: bool| {
+                let start = old.find(from).unwrap();
+                let new = format!("{}{}{}", &old[..start], to, &old[start + from.len()..]);
+                let edit = Edit {
+                    start,
+                    old_end: start + from.len(),
+                    new_end: start + to.len(),
+                };
+                let mut tree = builder.parse(old.to_string()).unwrap();
+                tree.rename_tag("em", "em-mark");
+                builder.reparse(&mut tree, edit, &new).unwrap();
+                assert_eq!(
+                    tree.query_selector("em-mark").unwrap().is_none(),
+                    full,
+                    "{}",
+                    new
+                );
+                tree.rename_tag("em-mark", "em");
+                let expected = builder.parse(new.clone()).unwrap();
+                assert_eq!(html_and_spans(&tree), html_and_spans(&expected), "{}", new);
+            };
+            let strict = ParserBuilder::new();
+            // Inside text: only the `<p>` is parsed again.
+            check(
+                &strict,
+                "<main><p>hello</p><em>e</em></main>",
+                "ello",
+                "allo there",
+                false,
+            );
+            // Splitting the `<p>` changes its extent, so its parent is tried.
+            check(
+                &strict,
+                "<main><div><p>ab</p></div><em>e</em></main>",
+                "b",
+                "</p><p>b",
+                false,
+            );
+            // The `<br>` comes out as an `<img>`, so its parent is tried.
+            check(
+                &strict,
+                "<main><div><p><br>x</p></div><em>e</em></main>",
+                "br",
+                "img",
+                false,
+            );
+            // No element holds an edit at the top level.
+            check(&strict, "<p>a</p><em>e</em>", "<em>", "<p>b</p><em>", true);
+            check(
+                &strict,
+                "<p>a</p><em>e</em>",
+                "</em>",
+                "</em><p>b</p>",
+                true,
+            );
+            // Recovery depends on everything before it.
+            let recover = ParserBuilder::new().recover(true);
+            check(
+                &recover,
+                "<main><p>a<b>x</p><em>e</em></main>",
+                "x",
+                "y",
+                true,
+            );
+
+            let mut tree = strict
+                .parse("<main><div>a</div></main>".to_string())
+                .unwrap();
+            let edit = Edit {
+                start: 11,
+                old_end: 12,
+                new_end: 17,
+            };
+            assert!(strict
+                .reparse(&mut tree, edit, "<main><div><span</div></main>")
+                .is_err());
+            assert_eq!(tree.to_html(), "<main><div>a</div></main>");
+        }
+
+        #[test]
+        fn font_shorthand_and_longhands_in_cascade_order() {
+            let root = source("<p class=\"a\">x</p>".to_string()).unwrap();
+            let font = |css: &str| {
+                style_tree(&root, &[parse_css(css).unwrap()])
+                    .font()
+                    .unwrap()
+            };
+            let px = |size| Value::Length(size, Unit::Px);
+            assert_eq!(
+                font("p { font-size: 12px; font: 20px serif }").size,
+                px(20.0)
+            );
+            assert_eq!(
+                font("p { font: 20px serif; font-size: 12px }").size,
+                px(12.0)
+            );
+            assert_eq!(
+                font("p.a { font-size: 12px } p { font: 20px serif }").size,
+                px(12.0)
+            );
+            // The shorthand resets what it leaves out.
+            let reset = font("p { font-weight: bold; font-style: italic; font: 12px serif }");
+            assert_eq!((reset.weight, reset.style), (400, FontStyle::Normal));
+            let styled = style_tree(
+                &root,
+                &[parse_css("p { line-height: 2; font: 12px/1.5 serif }").unwrap()],
+            );
+            assert_eq!(
+                styled.value("line-height"),
+                Some(&Value::Keyword("1.5".to_string()))
+            );
+        }
+
+        #[test]
+        fn query_selector_all_root() {
+            let root = source(
+                "<html class=\"doc\"><body><p>a</p><div><p>b</p></div></body></html>".to_string(),
+            )
+            .unwrap();
+            let tags = |selector: &str| -> Vec<String> {
+                root.query_selector_all(selector)
+                    .unwrap()
+                    .iter()
+                    .map(|node| node.element().unwrap().tag_name().to_string())
+                    .collect()
+            };
+            assert_eq!(tags(":root"), ["html"]);
+            assert_eq!(tags("html:root, p"), ["html", "p", "p"]);
+            assert_eq!(tags(".doc:root"), ["html"]);
+            assert!(tags("body:root").is_empty());
+            assert!(tags(".other:root").is_empty());
+            assert_eq!(tags(":root > body"), ["body"]);
+            assert_eq!(tags(":root > body > p"), ["p"]);
+            assert_eq!(tags(":not(:root)").len(), 4);
+            let root = source("<p>text</p>".to_string()).unwrap();
+            assert_eq!(
+                root.query_selector(":root")
+                    .unwrap()
+                    .unwrap()
+                    .text_content(),
+                "text"
+            );
+        }
+
+        #[test]
+        fn cascade_priority_order() {
+            let root = source(r#"<p id="x" style="color: green">a</p>"#.to_string()).unwrap();
+            let color = |user_agent: &str, author: &str| {
+                let sheets = [
+                    parse_css(user_agent)
+                        .unwrap()
+                        .with_origin(Origin::UserAgent),
+                    parse_css(author).unwrap(),
+                ];
+                match style_tree(&root, &sheets).value("color") {
+                    Some(Value::Keyword(color)) => color.clone(),
+                    _ => panic!("no color"),
+                }
+            };
+            // Author beats user agent whatever the specificity.
+            let root_without_inline = source(r#"<p id="x">a</p>"#.to_string()).unwrap();
+            let sheets = [
+                parse_css("#x { color: red }")
+                    .unwrap()
+                    .with_origin(Origin::UserAgent),
+                parse_css("p { color: blue }").unwrap(),
+            ];
+            assert_eq!(
+                style_tree(&root_without_inline, &sheets).value("color"),
+                Some(&Value::Keyword("blue".to_string()))
+            );
+            // Inline beats any author selector; important author beats inline.
+            assert_eq!(color("", "#x { color: blue }"), "green");
+            assert_eq!(color("", "p { color: blue !important }"), "blue");
+            // Within a level, specificity and then order decide.
+            assert_eq!(
+                color(
+                    "",
+                    "#x { color: red !important } p { color: blue !important }"
+                ),
+                "red"
+            );
+            assert_eq!(
+                color(
+                    "",
+                    "p { color: red !important } p { color: blue !important }"
+                ),
+                "blue"
+            );
+            // Important user-agent declarations beat important author ones.
+            assert_eq!(
+                color(
+                    "p { color: red !important }",
+                    "#x { color: blue !important }"
+                ),
+                "red"
+            );
+            let root = source(r#"<p style="color: green !important">a</p>"#.to_string()).unwrap();
+            let sheets = [parse_css("#x, p { color: blue !important }").unwrap()];
+            assert_eq!(
+                style_tree(&root, &sheets).value("color"),
+                Some(&Value::Keyword("green".to_string()))
+            );
+            let sheets = [parse_css("p { color: red !important }")
+                .unwrap()
+                .with_origin(Origin::UserAgent)];
+            assert_eq!(
+                style_tree(&root, &sheets).value("color"),
+                Some(&Value::Keyword("red".to_string()))
+            );
+        }
+
+        #[test]
+        fn minify_drops_overridden_declarations() {
+            let minify = |css: &str| parse_css(css).unwrap().minify();
+            assert_eq!(
+                minify("p { color: red; margin: 0; color: blue }"),
+                "p{margin:0;color:blue}"
+            );
+            assert_eq!(
+                minify("p { color: red !important; color: blue }"),
+                "p{color:red!important}"
+            );
+            assert_eq!(
+                minify("p { color: red !important; color: blue !important }"),
+                "p{color:blue!important}"
+            );
+            assert_eq!(minify("p { } a { color: red }"), "a{color:red}");
+        }
+
+        #[test]
+        fn minify_merges_rules_only_when_nothing_between_competes() {
+            let minify = |css: &str| parse_css(css).unwrap().minify();
+            assert_eq!(
+                minify("a { color: red } b { margin: 0 } c { color: red }"),
+                "a,c{color:red}b{margin:0}"
+            );
+            assert_eq!(
+                minify("a { color: red } b { color: blue } c { color: red }"),
+                "a{color:red}b{color:blue}c{color:red}"
+            );
+            assert_eq!(
+                minify("a { color: red; margin: 0 } c { margin: 0; color: red }"),
+                "a{color:red;margin:0}c{margin:0;color:red}"
+            );
+            assert_eq!(minify("a { color: red } a { color: red }"), "a{color:red}");
+        }
+
+        #[test]
+        fn minified_stylesheets_are_equivalent() {
+            let source = "div > p.intro ,  a:not(.x) + b { color : red ; margin: 0 !important }\n\
+                 ul li  ~  li { border: 1px solid #ccc }\n\
+                 @media print { p { display: none } }\n";
+            let css = parse_css(source).unwrap();
+            let minified = parse_css(&css.minify()).unwrap();
+            assert!(css.diff(&minified).is_empty(), "{:?}", css.diff(&minified));
+            assert_eq!(minified.minify(), css.minify());
+
+            // Merged rules change the selector lists, so compare the cascade.
+            let source = "p { color: red } .a { margin: 0 } div { color: red } \
+                 .b { color: blue; color: green } p.b { color: red }";
+            let root = source_tree();
+            let css = parse_css(source).unwrap();
+            let minified = parse_css(&css.minify()).unwrap();
+            assert_eq!(
+                css.minify(),
+                "p,div{color:red}.a{margin:0}.b{color:green}p.b{color:red}"
+            );
+            let before = style_tree(&root, &[css]);
+            let after = style_tree(&root, &[minified]);
+            let values = |styled: &StyledNode| -> Vec<PropertyMap> {
+                let mut all = vec![styled.specified_values.clone()];
+                let mut stack: Vec<&StyledNode> = styled.children.iter().collect();
+                while let Some(node) = stack.pop() {
+                    all.push(node.specified_values.clone());
+                    stack.extend(node.children.iter());
+                }
+                all
+            };
+            assert_eq!(values(&before), values(&after));
+        }
+
+        fn source_tree() -> Node {
+            source(
+                r#"<div class="b"><p class="a">1</p><p class="b">2</p><div class="a b">3</div></div>"#
+                    .to_string(),
+            )
+            .unwrap()
+        }
+
+        #[test]
+        fn optional_end_tags() {
+            let html = |source: &str| super::source(source.to_string()).unwrap().to_html();
+            assert_eq!(html("<ul><li>a<li>b</ul>"), "<ul><li>a</li><li>b</li></ul>");
+            assert_eq!(
+                html("<dl><dt>term<dd>one<dd>two<dt>next</dl>"),
+                "<dl><dt>term</dt><dd>one</dd><dd>two</dd><dt>next</dt></dl>"
+            );
+            assert_eq!(
+                html("<table><tr><td>1<td>2<tr><td>3</table>"),
+                "<table><tr><td>1</td><td>2</td></tr><tr><td>3</td></tr></table>"
+            );
+            assert_eq!(
+                html("<select><option>a<option>b<optgroup><option>c</select>"),
+                "<select><option>a</option><option>b</option>\
+                 <optgroup><option>c</option></optgroup></select>"
+            );
+            assert_eq!(
+                html("<div><p>one<div>two</div><p>three<ul><li>x</ul></div>"),
+                "<div><p>one</p><div>two</div><p>three</p><ul><li>x</li></ul></div>"
+            );
+            // Only the listed start tags close a <p>; inline content stays in it.
+            assert_eq!(
+                html("<p>a<b>b</b><span>c</span></p>"),
+                "<p>a<b>b</b><span>c</span></p>"
+            );
+            // Written end tags are still accepted.
+            assert_eq!(
+                html("<ul><li>a</li><li>b</li></ul>"),
+                "<ul><li>a</li><li>b</li></ul>"
+            );
+        }
+
+        #[test]
+        fn misnested_formatting_elements_are_reopened() {
+            let recover = |source: &str| {
+                ParserBuilder::new()
+                    .recover(true)
+                    .parse_with_warnings(source.to_string())
+                    .map(|(root, warnings)| (root.to_html(), warnings.len()))
+                    .unwrap()
+            };
+            let (html, warnings) = recover("<b><i>text</b></i>");
+            assert_eq!(html, "<b><i>text</i></b>");
+            assert!(warnings > 0);
+            let (html, _) = recover("<div><b><i>one</b>two</i></div>");
+            assert_eq!(html, "<div><b><i>one</i></b><i>two</i></div>");
+            let (html, _) = recover("<p><b><i>one</b>two</i>three</p>");
+            assert_eq!(html, "<p><b><i>one</i></b><i>two</i>three</p>");
+            // Well-nested input is untouched.
+            assert_eq!(
+                recover("<b><i>x</i></b>"),
+                ("<b><i>x</i></b>".to_string(), 0)
+            );
+            // Strict mode still rejects misnesting.
+            assert!(source("<b><i>text</b></i>".to_string()).is_err());
+        }
+
+        #[test]
+        fn parser_limits() {
+            let message = |builder: ParserBuilder, source: &str| {
+                builder.parse(source.to_string()).err().map(|e| e.message)
+            };
+            let wide = "<p></p>".repeat(10);
+            assert!(ParserBuilder::new()
+                .max_nodes(10)
+                .parse(wide.clone())
+                .is_ok());
+            assert_eq!(
+                message(ParserBuilder::new().max_nodes(9), &wide).as_deref(),
+                Some("document has more than 9 nodes")
+            );
+            let deep = "<div>".repeat(5) + &"</div>".repeat(5);
+            assert!(ParserBuilder::new()
+                .max_depth(5)
+                .parse(deep.clone())
+                .is_ok());
+            assert_eq!(
+                message(ParserBuilder::new().max_depth(4), &deep).as_deref(),
+                Some("elements nested more than 4 deep")
+            );
+            let attributes = r#"<p a="1" b="2" c="3"></p>"#;
+            assert!(ParserBuilder::new()
+                .max_attributes(3)
+                .parse(attributes.to_string())
+                .is_ok());
+            assert_eq!(
+                message(ParserBuilder::new().max_attributes(2), attributes).as_deref(),
+                Some("element has more than 2 attributes")
+            );
+            assert!(ParserBuilder::new()
+                .max_input_size(wide.len())
+                .parse(wide.clone())
+                .is_ok());
+            let Err(error) = ParserBuilder::new()
+                .max_input_size(wide.len() - 1)
+                .parse(wide.clone())
+            else {
+                panic!("input over the size limit parsed");
+            };
+            assert_eq!(
+                error.message,
+                format!("input is larger than {} bytes", wide.len() - 1)
+            );
+        }
+
+        #[test]
+        fn implied_structure_builds_head_and_body() {
+            let implied = |source: &str| {
+                ParserBuilder::new()
+                    .implied_structure(true)
+                    .parse(source.to_string())
+                    .unwrap()
+            };
+            let root =
+                implied("<title>T</title><meta charset=\"utf-8\"><p>one</p><link rel=\"x\">");
+            assert_eq!(matched_text(&root, "html > head > title"), ["T"]);
+            assert_eq!(root.query_selector_all("head > meta").unwrap().len(), 1);
+            assert_eq!(matched_text(&root, "html > body > p"), ["one"]);
+            // Only leading metadata goes to the head.
+            assert!(root.query_selector_all("head > link").unwrap().is_empty());
+            assert_eq!(root.query_selector_all("body > link").unwrap().len(), 1);
+
+            let root = implied("text");
+            assert_eq!(root.query_selector_all("html > head").unwrap().len(), 1);
+            assert_eq!(matched_text(&root, "html > body"), ["text"]);
+
+            // Explicit structure is kept, not nested again.
+            let root = implied("<html><head><title>T</title></head><body><p>x</p></body></html>");
+            assert_eq!(root.element().map(|e| &*e.tag_name), Some("html"));
+            assert!(root.query_selector_all("html").unwrap().is_empty());
+            assert_eq!(root.query_selector_all("html > body").unwrap().len(), 1);
+            assert_eq!(matched_text(&root, "head > title"), ["T"]);
+            assert_eq!(matched_text(&root, "body > p"), ["x"]);
+
+            // Off by default.
+            let root = source("<p>x</p>".to_string()).unwrap();
+            assert!(root.query_selector_all("body").unwrap().is_empty());
+        }
+
+        #[test]
+        fn css_wide_keywords() {
+            let root =
+                source(r#"<div><p>a</p><span>b</span><em>c</em></div>"#.to_string()).unwrap();
+            let css = parse_css(
+                "div { color: red; margin: 4px; display: block } \
+                 p { color: blue; margin: inherit; display: inherit } \
+                 span { color: initial; margin: unset; display: initial } \
+                 em { color: unset; margin: 2px; display: unset }",
+            )
+            .unwrap();
+            let styled = style_tree(&root, &[css]);
+            let [p, span, em] = styled.children() else {
+                panic!("expected three children");
+            };
+            let keyword = |k: &str| Some(Value::Keyword(k.to_string()));
+            let px = |n: f32| Some(Value::Length(n, Unit::Px));
+            assert_eq!(p.value("color").cloned(), keyword("blue"));
+            assert_eq!(p.value("margin").cloned(), px(4.0));
+            assert_eq!(p.value("display").cloned(), keyword("block"));
+            let black = Value::ColorValue(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255,
+            });
+            assert_eq!(span.value("color"), Some(&black));
+            // `margin` isn't inherited, so `unset` means `initial`.
+            assert_eq!(span.value("margin").cloned(), px(0.0));
+            assert_eq!(span.value("display").cloned(), keyword("inline"));
+            // `color` is inherited, so `unset` means `inherit`.
+            assert_eq!(em.value("color").cloned(), keyword("red"));
+            assert_eq!(em.value("display").cloned(), keyword("inline"));
+
+            // At the root, `inherit` falls back to the initial value, and a
+            // property with neither is dropped.
+            let root = source("<p>a</p>".to_string()).unwrap();
+            let css = parse_css("p { color: inherit; cursor: inherit }").unwrap();
+            let styled = style_tree(&root, &[css]);
+            assert_eq!(styled.value("color"), Some(&black));
+            assert_eq!(styled.value("cursor"), None);
+        }
+
+        #[test]
+        fn custom_properties_and_var() {
+            let root = source(
+                r#"<div class="theme"><p>a</p><p class="local">b</p><p class="fallback">c</p></div>"#
+                    .to_string(),
+            )
+            .unwrap();
+            let css = parse_css(
+                ".theme { --accent: #ff0000; --gap: 8px; --alias: var(--accent) } \
+                 p { color: var(--accent); margin: var(--gap) } \
+                 .local { --accent: blue; border-color: var(--alias) } \
+                 .fallback { color: var(--missing, green); margin: var(--missing) }",
+            )
+            .unwrap();
+            let styled = style_tree(&root, &[css]);
+            let [p, local, fallback] = styled.children() else {
+                panic!("expected three children");
+            };
+            let red = Value::ColorValue(Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 255,
+            });
+            // Custom properties are inherited from the nearest ancestor.
+            assert_eq!(p.value("color"), Some(&red));
+            assert_eq!(p.value("margin"), Some(&Value::Length(8.0, Unit::Px)));
+            assert_eq!(
+                p.value("--accent"),
+                Some(&Value::Keyword("#ff0000".to_string()))
+            );
+            // The element's own value wins, and aliases resolve where declared.
+            assert_eq!(
+                local.value("color"),
+                Some(&Value::Keyword("blue".to_string()))
+            );
+            assert_eq!(local.value("border-color"), Some(&red));
+            // The fallback is used when the property is missing; without one
+            // the declaration acts as `unset`.
+            assert_eq!(
+                fallback.value("color"),
+                Some(&Value::Keyword("green".to_string()))
+            );
+            assert_eq!(
+                fallback.value("margin"),
+                Some(&Value::Length(0.0, Unit::Px))
+            );
+
+            // References that loop resolve to nothing rather than hanging.
+            let root = source("<p>a</p>".to_string()).unwrap();
+            let css = parse_css("p { --a: var(--b); --b: var(--a); color: var(--a) }").unwrap();
+            let styled = style_tree(&root, &[css]);
+            assert_eq!(
+                styled.value("color"),
+                Some(&Value::ColorValue(Color {
+                    r: 0,
+                    g: 0,
+                    b: 0,
+                    a: 255,
+                }))
+            );
+        }
+
+        #[test]
+        fn selector_set_matches_like_each_selector() {
+            let root = source(
+                r#"<div id="main" class="box wide"><p class="box">a</p><span>b</span><em data-x="1">c</em></div>"#
+                    .to_string(),
+            )
+            .unwrap();
+            let set = SelectorSet::parse(
+                "#main, .box, .wide, p, span.box, [data-x], *, div#main.box, p#main, .missing",
+            )
+            .unwrap();
+            let mut elements = vec![root.element().unwrap()];
+            elements.extend(root.children().iter().filter_map(Node::element));
+            for elem in elements {
+                let expected: Vec<usize> = set
+                    .selectors()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, selector)| selector.matches(elem))
+                    .map(|(index, _)| index)
+                    .collect();
+                assert_eq!(set.matching_indices(elem), expected);
+            }
+            let matched_selectors = |elem: &ElementData| -> Vec<String> {
+                set.matching_indices(elem)
+                    .into_iter()
+                    .map(|index| set.selectors()[index].to_string())
+                    .collect()
+            };
+            let p = root.children()[0].element().unwrap();
+            let mut matched = matched_selectors(p);
+            matched.sort();
+            assert_eq!(matched, ["*", ".box", "p"]);
+            assert!(SelectorSet::parse("p >").is_err());
+        }
+
+        #[test]
+        fn paths_address_nodes() {
+            let root =
+                source("<div><p>a</p><ul><li>1</li><li>2<b>x</b></li></ul></div>".to_string())
+                    .unwrap();
+            let b = root.get_by_path(&[1, 1, 1]).unwrap();
+            assert_eq!(b.to_html(), "<b>x</b>");
+            assert_eq!(root.path_of(b), Some(vec![1, 1, 1]));
+            assert_eq!(root.path_of(&root), Some(vec![]));
+            assert!(std::ptr::eq(root.get_by_path(&[]).unwrap(), &root));
+            assert!(root.get_by_path(&[2]).is_none());
+            assert!(root.get_by_path(&[0, 0, 0]).is_none());
+            // Every node's path leads back to it.
+            let mut stack = vec![&root];
+            while let Some(node) = stack.pop() {
+                let path = root.path_of(node).unwrap();
+                assert!(std::ptr::eq(root.get_by_path(&path).unwrap(), node));
+                stack.extend(node.children());
+            }
+            // An equal node elsewhere is not the same node.
+            let copy = source(root.to_html()).unwrap();
+            assert_eq!(root.path_of(copy.get_by_path(&[1, 1, 1]).unwrap()), None);
+        }
+
+        #[test]
+        fn closest_walks_up_from_the_node() {
+            let root = source(
+                r#"<table class="t"><tr><td><span id="s">x</span></td></tr></table>"#.to_string(),
+            )
+            .unwrap();
+            let dom = Dom::new(&root);
+            let span = (0..)
+                .find(|&id| dom.node(id).element().and_then(ElementData::id) == Some("s"))
+                .unwrap();
+            let tag = |id: Option<NodeId>| {
+                id.and_then(|id| dom.node(id).element())
+                    .map(|elem| elem.tag_name.to_string())
+            };
+            // The node itself counts.
+            assert_eq!(tag(dom.closest(span, "span").unwrap()), Some("span".into()));
+            assert_eq!(tag(dom.closest(span, "td").unwrap()), Some("td".into()));
+            assert_eq!(
+                tag(dom.closest(span, "table.t").unwrap()),
+                Some("table".into())
+            );
+            assert_eq!(
+                tag(dom.closest(span, "tr > td, table").unwrap()),
+                Some("td".into())
+            );
+            assert_eq!(dom.closest(span, "div").unwrap(), None);
+            // A text node has no selector to match but its ancestors do.
+            let text = dom.children(span)[0];
+            assert_eq!(tag(dom.closest(text, "td").unwrap()), Some("td".into()));
+            assert_eq!(dom.closest(dom.root(), "td").unwrap(), None);
+            assert!(dom.closest(span, "td >").is_err());
+        }
+
+        #[test]
+        fn pseudo_elements_come_last() {
+            for selector in [
+                "p::before",
+                "p.foo::before",
+                "div > p:first-child::after",
+                "p:before",
+            ] {
+                let parsed = parse_selector_list(selector).unwrap();
+                assert!(parsed[0].pseudo_element().is_some(), "{}", selector);
+            }
+            for (selector, pos) in [
+                ("p::before.foo", 9),
+                ("p::before::after", 9),
+                ("p::before span", 9),
+                ("p::before > span", 9),
+                ("p:before span", 8),
+                ("a, p::before + b", 12),
+            ] {
+                let Err(error) = parse_selector_list(selector) else {
+                    panic!("{} parsed", selector);
+                };
+                assert_eq!(
+                    error.message,
+                    "a pseudo-element must come last in a selector"
+                );
+                assert_eq!(error.pos, pos, "{}", selector);
+            }
+            assert!(parse_css("p::before span { color: red }").is_err());
+        }
+
+        #[test]
+        fn doctypes_are_only_skipped_at_the_top_level() {
+            let mut parser = Parser::new("<!DOCTYPE html><div><!DOCTYPE x></div>".to_string());
+            let root = parser.parse_document().unwrap();
+            assert_eq!(parser.doctype.as_deref(), Some("html"));
+            assert_eq!(root.to_html(), "<div><!--DOCTYPE x--></div>");
+            assert!(matches!(
+                root.children()[0].node_type(),
+                NodeType::Comment(ref data) if data == "DOCTYPE x"
+            ));
+        }
+
+        #[test]
+        fn parse_next_node_resumes_where_it_stopped() {
+            let mut parser =
+                Parser::new("<!doctype html> <p>a</p>text<b>x</b></div> rest".to_string());
+            let mut html = Vec::new();
+            while let Some(node) = parser.parse_next_node() {
+                let Ok(node) = node else {
+                    panic!("parse error");
+                };
+                html.push(node.to_html());
+                assert!(parser.input.ends_with(parser.remaining()));
+            }
+            assert_eq!(html, ["<p>a</p>", "text", "<b>x</b>"]);
+            assert_eq!(parser.doctype.as_deref(), Some("html"));
+            // A closing tag stops it until the caller moves past it.
+            assert_eq!(parser.remaining(), "</div> rest");
+            assert!(parser.parse_next_node().is_none());
+            parser.pos += "</div>".len();
+            let Some(Ok(rest)) = parser.parse_next_node() else {
+                panic!("no node after the closing tag");
+            };
+            assert_eq!(rest.to_html(), "rest");
+            assert!(parser.parse_next_node().is_none());
+            assert_eq!(parser.remaining(), "");
+        }
+
+        #[test]
+        fn markdown_link_destinations_and_code_spans() {
+            let markdown = |html: &str| to_markdown(&source(html.to_string()).unwrap());
+            assert_eq!(
+                markdown(r#"<p><a href="https://example.com/a_b?c=d">x</a></p>"#),
+                "[x](https://example.com/a_b?c=d)"
+            );
+            assert_eq!(
+                markdown(r#"<p><a href="/a b)c">x</a></p>"#),
+                "[x](</a b)c>)"
+            );
+            assert_eq!(
+                markdown(r#"<p><a href="a<b>\c">x</a></p>"#),
+                r"[x](<a\<b\>\\c>)"
+            );
+            assert_eq!(markdown(r#"<p><a href="a\_b">x</a></p>"#), r"[x](<a\\_b>)");
+            assert_eq!(markdown("<p><a href=\"a\nb\">x</a></p>"), "[x](<a%0Ab>)");
+            assert_eq!(markdown(r#"<p><a href="">x</a></p>"#), "[x](<>)");
+
+            assert_eq!(markdown("<p><code>a</code></p>"), "`a`");
+            assert_eq!(markdown("<p><code>a`b</code></p>"), "``a`b``");
+            assert_eq!(markdown("<p><code>a``b`c</code></p>"), "```a``b`c```");
+            assert_eq!(markdown("<p><code>`a</code></p>"), "`` `a ``");
+            assert_eq!(markdown("<p><code>a`</code></p>"), "`` a` ``");
+            assert_eq!(markdown("<p><code>``</code></p>"), "` `` `");
+            let code = Node::new_element("code", &[], vec![Node::new_text(" a ")]);
+            let p = Node::new_element("p", &[], vec![code]);
+            assert_eq!(to_markdown(&p), "`  a  `");
+        }
+
+        /// A tree for the round-trip property, kept to what serializes
+        /// unambiguously: no element whose start tag ends another, no raw
+        /// text, and text that doesn't start with whitespace, which the
+        /// parser skips between nodes.
+        #[derive(Debug, Clone)]
+        enum GenNode {
+            Text(String),
+            Comment(String),
+            Element(&'static str, Vec<(&'static str, String)>, Vec<GenNode>),
+        }
+
+        const GEN_TAGS: &[&str] = &[
+            "div",
+            "span",
+            "b",
+            "i",
+            "em",
+            "section",
+            "custom-el",
+            "title",
+            "br",
+            "img",
+        ];
+        const GEN_ATTRIBUTES: &[&str] = &["id", "class", "href", "data-x", "hidden"];
+
+        fn gen_node() -> impl Strategy<Value = GenNode> {
+            let leaf = prop_oneof![
+                "[a-zA-Z0-9&<>\"';#]([a-zA-Z0-9&<>\"';# \u{a0}\u{e9}]{0,10})"
+                    .prop_map(GenNode::Text),
+                "[a-z <&]{0,8}".prop_map(GenNode::Comment),
+            ];
+            leaf.prop_recursive(4, 32, 4, |inner| {
+                let attribute = (prop::sample::select(GEN_ATTRIBUTES), "[a-z &<>\"'=]{0,6}");
+                (
+                    prop::sample::select(GEN_TAGS),
+                    prop::collection::vec(attribute, 0..3),
+                    prop::collection::vec(inner, 0..4),
+                )
+                    .prop_map(|(tag, attributes, children)| {
+                        GenNode::Element(tag, attributes, children)
+                    })
+            })
+        }
+
+        /// Builds the nodes, merging adjacent text as parsing would.
+        fn build_nodes(nodes: &[GenNode], parent: &str) -> Vec<Node> {
+            let mut built: Vec<Node> = Vec::new();
+            for node in nodes {
+                let node = match node {
+                    // Only text in a `<title>`; void elements have nothing.
+                    _ if matches!(parent, "br" | "img") => continue,
+                    GenNode::Text(data) => text(data.clone()),
+                    _ if parent == "title" => continue,
+                    GenNode::Comment(data) => comment(data.clone()),
+                    GenNode::Element(tag, attributes, children) => {
+                        let attributes = attributes
+                            .iter()
+                            .map(|&(name, ref value)| (Rc::from(name), value.clone()))
+                            .collect();
+                        elem(*tag, attributes, build_nodes(children, tag))
+                    }
+                };
+                if let (Some(last), NodeType::Text(ref data)) = (built.last_mut(), &node.node_type)
+                {
+                    if let NodeType::Text(ref mut previous) = last.node_type {
+                        previous.push_str(data);
+                        continue;
+                    }
+                }
+                built.push(node);
+            }
+            built
+        }
+
+        /// Whether two trees have the same nodes, tags, attributes and text,
+        /// ignoring spans and attribute order.
+        fn same_tree(a: &Node, b: &Node) -> bool {
+            let same_node = match (&a.node_type, &b.node_type) {
+                (NodeType::Text(a), NodeType::Text(b)) => a == b,
+                (NodeType::Comment(a), NodeType::Comment(b)) => a == b,
+                (NodeType::Element(a), NodeType::Element(b)) => {
+                    a.tag_name == b.tag_name && a.attributes == b.attributes
+                }
+                _ => false,
+            };
+            same_node
+                && a.children.len() == b.children.len()
+                && a.children
+                    .iter()
+                    .zip(&b.children)
+                    .all(|(a, b)| same_tree(a, b))
+        }
+
+        proptest! {
+            #[test]
+            fn serialized_trees_parse_back(children in prop::collection::vec(gen_node(), 0..5)) {
+                let tree = elem("div", HashMap::new(), build_nodes(&children, "div"));
+                let html = tree.to_html();
+                let parsed = source(html.clone()).unwrap();
+                prop_assert!(same_tree(&tree, &parsed), "{}\n{}", html, parsed.to_html());
+            }
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! The tree API as another crate sees it.
+
+use stupid_webbrowser::dom::{Node, NodeType, ParserBuilder};
+
+#[test]
+fn read_a_parsed_tree() {
+    let root = ParserBuilder::new()
+        .parse(r#"<ul id="list"><li class="a">one</li><!--x--><li>two</li></ul>"#.to_string())
+        .unwrap();
+    let ul = root.element().unwrap();
+    assert_eq!(ul.tag_name(), "ul");
+    assert_eq!(ul.get_attribute("id"), Some("list"));
+    assert_eq!(root.children().len(), 3);
+    assert!(matches!(root.children()[1].node_type(), NodeType::Comment(text) if text == "x"));
+
+    let first = root.query_selector("li").unwrap().unwrap();
+    assert_eq!(first.element().unwrap().get_attribute("class"), Some("a"));
+    match first.children()[0].node_type() {
+        NodeType::Text(text) => assert_eq!(text, "one"),
+        _ => panic!("expected text"),
+    }
+    let tags: Vec<&str> = root
+        .walk()
+        .filter_map(|(_, node)| node.element())
+        .map(|elem| elem.tag_name())
+        .collect();
+    assert_eq!(tags, ["ul", "li", "li"]);
+}
+
+#[test]
+fn assemble_a_tree() {
+    let mut list = Node::new_element("ul", &[("id", "list")], Vec::new());
+    list.append_child(Node::new_element("li", &[], vec![Node::new_text("b & c")]));
+    assert!(list.insert_child_at(0, Node::new_comment("items")).is_ok());
+    assert!(list.insert_child_at(5, Node::new_text("x")).is_err());
+    assert_eq!(
+        list.to_html(),
+        r#"<ul id="list"><!--items--><li>b &amp; c</li></ul>"#
+    );
+    assert_eq!(list.remove_child(0).unwrap().to_html(), "<!--items-->");
+    assert!(list.remove_child(1).is_none());
+}